@@ -0,0 +1,51 @@
+//! Bytes saved by compressing the full membership list a node gossips in its pings.
+//! Run with `cargo bench --bench compression`.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+use swim_app::compression::swim_node::{decode_frame, encode_frame, Compression};
+use swim_app::coordinate::swim_node::Coordinate;
+use swim_app::member_node::swim_node::{MemberNodeDetails, MemberNodeState, MemberNodesRegistry};
+use swim_app::message::swim_node::Message;
+
+const ITERATIONS: u32 = 100;
+
+fn registry(size: u16) -> MemberNodesRegistry {
+    let mut members = MemberNodesRegistry::new();
+    for host in 1..=size {
+        members.add(host, u32::from(host % 4));
+        members.set_tags(host, BTreeMap::from([
+            (String::from("zone"), format!("eu-west-{}", host % 3)),
+            (String::from("role"), String::from(if host % 5 == 0 { "db" } else { "web" })),
+        ]));
+        if host % 17 == 0 {
+            members.set_node_state(host, MemberNodeState::Failed);
+        }
+    }
+    members
+}
+
+fn main() {
+    let compression = Compression::default();
+    println!("{:>8} {:>10} {:>12} {:>7} {:>14} {:>16}", "members", "raw bytes", "compressed", "saved", "compress (us)", "decompress (us)");
+    for size in [10, 100, 1_000, 10_000] {
+        let message = Message::Ping(MemberNodeDetails::with_members(0, registry(size)), None, Coordinate::new()).encode();
+
+        let started = Instant::now();
+        let mut frame = Vec::new();
+        for _ in 0..ITERATIONS {
+            frame = encode_frame(&message, Some(&compression), true);
+        }
+        let compress = started.elapsed() / ITERATIONS;
+
+        let started = Instant::now();
+        for _ in 0..ITERATIONS {
+            assert_eq!(Ok(message.len()), decode_frame(&frame).map(|m| m.len()));
+        }
+        let decompress = started.elapsed() / ITERATIONS;
+
+        let saved = 100.0 * (1.0 - frame.len() as f64 / message.len() as f64);
+        println!("{:>8} {:>10} {:>12} {:>6.1}% {:>14} {:>16}",
+                 size, message.len(), frame.len(), saved, compress.as_micros(), decompress.as_micros());
+    }
+}
@@ -0,0 +1,87 @@
+pub mod swim_node {
+    use std::sync::{Arc, Mutex};
+    use tokio::runtime::Handle;
+    use crate::connection::swim_node::{send_all, ConnectionRegistry};
+    use crate::member_node::swim_node::{DefaultMemberNode, MemberNodeHandle, NodeConfig};
+    use crate::message::swim_node::Message;
+    use crate::network_router::NodeFactory;
+    use crate::log;
+
+    /// Runs a `DefaultMemberNode` as two tasks on a tokio runtime instead of two OS threads,
+    /// so that thousands of nodes can share a small thread pool.
+    pub struct AsyncMemberNode;
+
+    impl AsyncMemberNode {
+        pub fn spawn(host: u16, connection: Arc<Mutex<dyn ConnectionRegistry>>, runtime: &Handle) -> MemberNodeHandle {
+            AsyncMemberNode::spawn_with_config(host, NodeConfig::default(), connection, runtime)
+        }
+
+        pub fn spawn_with_config(host: u16, config: NodeConfig, connection: Arc<Mutex<dyn ConnectionRegistry>>, runtime: &Handle) -> MemberNodeHandle {
+            AsyncMemberNode::spawn_with_incarnation(host, 0, config, connection, runtime)
+        }
+
+        /// Spawns a node which announces itself with the given incarnation, e.g. after a restart.
+        pub fn spawn_with_incarnation(host: u16, incarnation: u32, config: NodeConfig,
+                                      connection: Arc<Mutex<dyn ConnectionRegistry>>, runtime: &Handle) -> MemberNodeHandle {
+            let ping_interval = config.ping_interval;
+            let (sender, receiver) = connection.lock().unwrap().inbox();
+            let ticker = sender.clone();
+            connection.lock().unwrap().add_connection(host, sender.clone());
+
+            runtime.spawn(async move {
+                let mut node = DefaultMemberNode::with_incarnation(host, incarnation, config);
+                send_all(&connection, host, node.rejoin());
+                loop {
+                    node.expire_pending();
+                    let next = receiver.recv_next_async(node.is_paused());
+                    let message = match node.next_deadline() {
+                        Some(deadline) => match tokio::time::timeout_at(deadline.into(), next).await {
+                            Ok(message) => message,
+                            Err(_) => continue,
+                        },
+                        None => next.await,
+                    };
+                    let message = match message {
+                        Some(message) => message,
+                        None => break,
+                    };
+                    if let Message::Shutdown() = message {
+                        log!("Node {} received termination message", &host);
+                        break;
+                    }
+                    send_all(&connection, host, node.handle_message(message));
+                }
+            });
+
+            runtime.spawn(async move {
+                let mut interval = tokio::time::interval(ping_interval);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if !ticker.send(Message::Tick()) {
+                        break;
+                    }
+                }
+            });
+            MemberNodeHandle::new(host, Arc::new(sender))
+        }
+    }
+
+    /// Creates the nodes of a router as tasks on the runtime.
+    pub struct AsyncNodeFactory {
+        config: NodeConfig,
+        runtime: Handle,
+    }
+
+    impl AsyncNodeFactory {
+        pub fn new(config: NodeConfig, runtime: Handle) -> AsyncNodeFactory {
+            AsyncNodeFactory { config, runtime }
+        }
+    }
+
+    impl NodeFactory<MemberNodeHandle> for AsyncNodeFactory {
+        fn create(&self, host: u16, incarnation: u32, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> MemberNodeHandle {
+            AsyncMemberNode::spawn_with_incarnation(host, incarnation, self.config.clone(), connection, &self.runtime)
+        }
+    }
+}
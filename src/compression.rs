@@ -0,0 +1,81 @@
+pub mod swim_node {
+    /// Flags in the high bits of the type byte of an encoded message, nodes ignore the flags they don't know.
+    /// The payload after the type byte is an LZ4 block, preceded by its uncompressed length.
+    pub const FLAG_COMPRESSED: u8 = 0x80;
+    /// The sender reads compressed messages, so its peers may compress the messages they send it.
+    pub const FLAG_ACCEPTS_COMPRESSION: u8 = 0x40;
+    const FLAGS: u8 = FLAG_COMPRESSED | FLAG_ACCEPTS_COMPRESSION;
+    const LENGTH_LEN: usize = 4;
+    /// Largest message a compressed frame may claim to hold, so a corrupted length can't exhaust the memory.
+    const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+    #[derive(Clone, Copy, Debug)]
+    pub struct Compression {
+        /// Encoded messages up to this size are sent as they are, compressing them isn't worth it.
+        pub threshold: usize,
+    }
+
+    impl Default for Compression {
+        fn default() -> Self {
+            Compression { threshold: 512 }
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub struct FrameError(pub String);
+
+    /// Turns an encoded message into the frame a node with the given compression settings sends. The message is
+    /// compressed only if it's larger than the threshold, the peer announced that it reads compressed messages
+    /// and the result is smaller. A node without compression sends the message unchanged.
+    pub fn encode_frame(message: &[u8], compression: Option<&Compression>, peer_accepts: bool) -> Vec<u8> {
+        let compression = match (compression, message.split_first()) {
+            (Some(compression), Some(_)) => compression,
+            _ => return message.to_vec(),
+        };
+        let (kind, payload) = (message[0] | FLAG_ACCEPTS_COMPRESSION, &message[1..]);
+        if peer_accepts && message.len() > compression.threshold {
+            let compressed = lz4_flex::block::compress(payload);
+            if 1 + LENGTH_LEN + compressed.len() < message.len() {
+                let mut frame = Vec::with_capacity(1 + LENGTH_LEN + compressed.len());
+                frame.push(kind | FLAG_COMPRESSED);
+                frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+                frame.extend_from_slice(&compressed);
+                return frame;
+            }
+        }
+        let mut frame = message.to_vec();
+        frame[0] = kind;
+        frame
+    }
+
+    /// Restores the encoded message of a frame, without the flags.
+    pub fn decode_frame(frame: &[u8]) -> Result<Vec<u8>, FrameError> {
+        let kind = match frame.first() {
+            Some(kind) => *kind,
+            None => return Ok(Vec::new()),
+        };
+        if kind & FLAG_COMPRESSED == 0 {
+            let mut message = frame.to_vec();
+            message[0] = kind & !FLAGS;
+            return Ok(message);
+        }
+        if frame.len() < 1 + LENGTH_LEN {
+            return Err(FrameError(String::from("truncated compressed frame")));
+        }
+        let length = u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize;
+        if length > MAX_MESSAGE_LEN {
+            return Err(FrameError(format!("compressed frame claims {} bytes", length)));
+        }
+        let payload = lz4_flex::block::decompress(&frame[1 + LENGTH_LEN..], length)
+            .map_err(|err| FrameError(err.to_string()))?;
+        let mut message = Vec::with_capacity(1 + payload.len());
+        message.push(kind & !FLAGS);
+        message.extend_from_slice(&payload);
+        Ok(message)
+    }
+
+    /// True if the sender of the frame reads compressed messages.
+    pub fn accepts_compression(frame: &[u8]) -> bool {
+        frame.first().is_some_and(|kind| kind & FLAG_ACCEPTS_COMPRESSION != 0)
+    }
+}
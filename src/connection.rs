@@ -1,49 +1,685 @@
 pub mod swim_node {
-    use std::collections::HashMap;
-    use std::sync::mpsc::Sender;
-    use crate::message::swim_node::Message;
+    use std::cmp;
+    use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use rand::thread_rng;
+    use crate::compression::swim_node::{accepts_compression, decode_frame, encode_frame, Compression, FLAG_COMPRESSED};
+    use crate::latency::swim_node::LatencyTopology;
+    use crate::message::swim_node::{Message, MessagePriority};
+    use crate::log;
+    use crate::metrics::swim_node::metrics;
+    use crate::rate_limit::swim_node::{RateLimit, RateLimiter};
+
+    pub const DEFAULT_INBOX_CAPACITY: usize = 1024;
 
     pub trait ConnectionRegistry: Send {
 
         fn send_to(&self, host: u16, message: Message);
 
-        fn add_connection(&mut self, host: u16, connection: Sender<Message>);
+        /// Sends a message on behalf of the `from` host, unless the network between the hosts is partitioned.
+        fn send_from(&self, from: u16, to: u16, message: Message) {
+            if let Some(route) = self.route(from, to) {
+                route.send(message);
+            }
+        }
+
+        /// The route of a message from one host to another, `None` if the network between them is partitioned.
+        fn route(&self, from: u16, to: u16) -> Option<Route>;
+
+        fn partition(&mut self, groups: Vec<Vec<u16>>);
+
+        fn heal(&mut self);
+
+        fn add_connection(&mut self, host: u16, connection: InboxSender);
 
         fn remove_connection(&mut self, host: u16);
+
+        fn inbox(&self) -> (InboxSender, InboxReceiver);
+    }
+
+    /// Sends the messages on behalf of the host, holding the lock of the registry only to look up their routes.
+    pub fn send_all(connection: &Mutex<dyn ConnectionRegistry>, from: u16, messages: Vec<(u16, Message)>) {
+        for (to, message) in messages {
+            let route = connection.lock().unwrap().route(from, to);
+            if let Some(route) = route {
+                route.send(message);
+            }
+        }
+    }
+
+    /// What a full inbox does with a new message.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum DropPolicy {
+        /// The new message is dropped.
+        DropNewest,
+        /// The oldest queued message is dropped to make room for the new one.
+        DropOldest,
+        /// The oldest message with the lowest priority is dropped if the new message has a higher priority,
+        /// so acks get through during a gossip storm.
+        PrioritizeAcks,
+    }
+
+    /// The messages of an inbox in one queue per priority, numbered in the order they arrived, so the
+    /// receiver still gets them in that order and a full inbox finds the message to drop right away.
+    #[derive(Default)]
+    struct Queued {
+        by_priority: [VecDeque<(u64, Message)>; 4],
+        next_seq: u64,
+    }
+
+    impl Queued {
+        fn len(&self) -> usize {
+            self.by_priority.iter().map(VecDeque::len).sum()
+        }
+
+        /// Number of queued network messages, the ones the capacity of the inbox applies to.
+        fn network_len(&self) -> usize {
+            self.by_priority[..MessagePriority::Control as usize].iter().map(VecDeque::len).sum()
+        }
+
+        fn push_back(&mut self, message: Message) {
+            let seq = self.next_seq;
+            self.next_seq += 1;
+            self.by_priority[message.priority() as usize].push_back((seq, message));
+        }
+
+        fn pop_front(&mut self) -> Option<Message> {
+            self.pop_oldest(MessagePriority::Control as usize + 1)
+        }
+
+        /// Removes the message which arrived first, or only the first control message if `control_only`.
+        fn pop_next(&mut self, control_only: bool) -> Option<Message> {
+            if control_only {
+                self.by_priority[MessagePriority::Control as usize].pop_front().map(|(_, message)| message)
+            } else {
+                self.pop_front()
+            }
+        }
+
+        /// Removes the message which arrived first among the lowest `priorities`.
+        fn pop_oldest(&mut self, priorities: usize) -> Option<Message> {
+            let queue = self.by_priority[..priorities].iter_mut()
+                .filter(|q| !q.is_empty())
+                .min_by_key(|q| q.front().map(|(seq, _)| *seq))?;
+            queue.pop_front().map(|(_, message)| message)
+        }
+
+        /// Removes a queued message to make room for a new network message of the given priority,
+        /// returns `false` if the new message has to be dropped instead.
+        fn evict(&mut self, policy: DropPolicy, priority: MessagePriority) -> bool {
+            match policy {
+                DropPolicy::DropNewest => false,
+                DropPolicy::DropOldest => self.pop_oldest(MessagePriority::Control as usize).is_some(),
+                DropPolicy::PrioritizeAcks => self.by_priority[..priority as usize].iter_mut()
+                    .find(|q| !q.is_empty())
+                    .and_then(VecDeque::pop_front)
+                    .is_some(),
+            }
+        }
+
+        /// Drops all messages, they no longer count towards the depth of the inboxes.
+        fn clear(&mut self) {
+            metrics().inbox_depth.sub(self.len() as i64);
+            self.by_priority.iter_mut().for_each(VecDeque::clear);
+        }
+    }
+
+    struct InboxQueue {
+        messages: Mutex<Queued>,
+        available: Condvar,
+        #[cfg(feature = "async")]
+        notify: tokio::sync::Notify,
+        capacity: usize,
+        policy: DropPolicy,
+        dropped: AtomicU64,
+        senders: AtomicUsize,
+    }
+
+    impl InboxQueue {
+        fn push(&self, message: Message) {
+            let mut messages = self.messages.lock().unwrap();
+            let priority = message.priority();
+            if priority != MessagePriority::Control && messages.network_len() >= self.capacity {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                metrics().messages_dropped.inc();
+                if !messages.evict(self.policy, priority) {
+                    return;
+                }
+            } else {
+                metrics().inbox_depth.inc();
+            }
+            messages.push_back(message);
+            self.wake();
+        }
+
+        fn wake(&self) {
+            self.available.notify_all();
+            #[cfg(feature = "async")]
+            self.notify.notify_one();
+        }
+    }
+
+    impl Drop for InboxQueue {
+        fn drop(&mut self) {
+            if let Ok(messages) = self.messages.get_mut() {
+                messages.clear();
+            }
+        }
+    }
+
+    /// Sending side of a bounded node inbox.
+    pub struct InboxSender {
+        queue: Arc<InboxQueue>,
+    }
+
+    /// Receiving side of a bounded node inbox.
+    pub struct InboxReceiver {
+        queue: Arc<InboxQueue>,
+    }
+
+    /// Creates a node inbox holding up to `capacity` network messages. Control messages of the node
+    /// itself (ticks, calls and shutdown) are never dropped.
+    pub fn inbox(capacity: usize, policy: DropPolicy) -> (InboxSender, InboxReceiver) {
+        let queue = Arc::new(InboxQueue {
+            messages: Mutex::new(Queued::default()),
+            available: Condvar::new(),
+            #[cfg(feature = "async")]
+            notify: tokio::sync::Notify::new(),
+            capacity,
+            policy,
+            dropped: AtomicU64::new(0),
+            senders: AtomicUsize::new(1),
+        });
+        (InboxSender { queue: Arc::clone(&queue) }, InboxReceiver { queue })
+    }
+
+    impl InboxSender {
+        /// Queues the message, returns `false` if the receiver is gone.
+        pub fn send(&self, message: Message) -> bool {
+            if Arc::strong_count(&self.queue) == self.queue.senders.load(Ordering::SeqCst) {
+                return false;
+            }
+            self.queue.push(message);
+            true
+        }
+
+        pub fn dropped(&self) -> u64 {
+            self.queue.dropped.load(Ordering::Relaxed)
+        }
+    }
+
+    impl Clone for InboxSender {
+        fn clone(&self) -> Self {
+            self.queue.senders.fetch_add(1, Ordering::SeqCst);
+            InboxSender { queue: Arc::clone(&self.queue) }
+        }
+    }
+
+    impl Drop for InboxSender {
+        fn drop(&mut self) {
+            self.queue.senders.fetch_sub(1, Ordering::SeqCst);
+            self.queue.wake();
+        }
+    }
+
+    impl InboxReceiver {
+        /// Blocks until a message is available, returns `None` once all senders are gone.
+        pub fn recv(&self) -> Option<Message> {
+            self.recv_next(false, None).ok()
+        }
+
+        /// Like `recv`, but leaves the network messages queued, e.g. while the node is paused. They still
+        /// count towards the capacity of the inbox, so a full inbox drops them by its policy.
+        pub fn recv_control(&self) -> Option<Message> {
+            self.recv_next(true, None).ok()
+        }
+
+        /// Blocks until a message is available, or only a control message if `control_only`, and gives up
+        /// once the deadline passed.
+        pub(crate) fn recv_next(&self, control_only: bool, deadline: Option<Instant>) -> Result<Message, RecvTimeoutError> {
+            let mut messages = self.queue.messages.lock().unwrap();
+            loop {
+                let now = Instant::now();
+                if deadline.is_some_and(|d| d <= now) {
+                    return Err(RecvTimeoutError::Timeout);
+                }
+                if let Some(message) = messages.pop_next(control_only) {
+                    metrics().inbox_depth.dec();
+                    return Ok(message);
+                }
+                if self.queue.senders.load(Ordering::SeqCst) == 0 {
+                    return Err(RecvTimeoutError::Disconnected);
+                }
+                messages = match deadline {
+                    Some(d) => self.queue.available.wait_timeout(messages, d - now).unwrap().0,
+                    None => self.queue.available.wait(messages).unwrap(),
+                };
+            }
+        }
+
+        /// Waits for a message without blocking the thread, for nodes running as tasks.
+        #[cfg(feature = "async")]
+        pub async fn recv_async(&self) -> Option<Message> {
+            self.recv_next_async(false).await
+        }
+
+        /// Like `recv_async`, or only a control message if `control_only`.
+        #[cfg(feature = "async")]
+        pub(crate) async fn recv_next_async(&self, control_only: bool) -> Option<Message> {
+            loop {
+                {
+                    let mut messages = self.queue.messages.lock().unwrap();
+                    if let Some(message) = messages.pop_next(control_only) {
+                        metrics().inbox_depth.dec();
+                        return Some(message);
+                    }
+                    if self.queue.senders.load(Ordering::SeqCst) == 0 {
+                        return None;
+                    }
+                }
+                self.queue.notify.notified().await;
+            }
+        }
+    }
+
+    impl Drop for InboxReceiver {
+        /// Discards the messages the node didn't get to, nothing reads them any more.
+        fn drop(&mut self) {
+            if let Ok(mut messages) = self.queue.messages.lock() {
+                messages.clear();
+            }
+        }
+    }
+
+    /// A message waiting for its delivery time.
+    struct Delayed {
+        deliver_at: Instant,
+        seq: u64,
+        to: u16,
+        inbox: InboxSender,
+        message: Message,
+    }
+
+    impl PartialEq for Delayed {
+        fn eq(&self, other: &Self) -> bool {
+            (self.deliver_at, self.seq) == (other.deliver_at, other.seq)
+        }
+    }
+
+    impl Eq for Delayed {}
+
+    impl PartialOrd for Delayed {
+        fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Delayed {
+        /// Reversed, so the heap pops the message due first, and messages due at the same time in the order they were sent.
+        fn cmp(&self, other: &Self) -> cmp::Ordering {
+            (other.deliver_at, other.seq).cmp(&(self.deliver_at, self.seq))
+        }
+    }
+
+    #[derive(Default)]
+    struct DelayQueue {
+        pending: BinaryHeap<Delayed>,
+        next_seq: u64,
+        stopped: bool,
+    }
+
+    /// Delivers messages to their inboxes once their delay has passed, on a thread of its own which stops
+    /// when the line is dropped. Messages still in flight at that point are lost.
+    struct DelayLine {
+        queue: Arc<(Mutex<DelayQueue>, Condvar)>,
+    }
+
+    impl DelayLine {
+        fn start() -> DelayLine {
+            let queue = Arc::new((Mutex::new(DelayQueue::default()), Condvar::new()));
+            let shared = Arc::clone(&queue);
+            thread::spawn(move || {
+                let (lock, available) = &*shared;
+                loop {
+                    let mut queue = lock.lock().unwrap();
+                    let due = loop {
+                        if queue.stopped {
+                            return;
+                        }
+                        let now = Instant::now();
+                        match queue.pending.peek().map(|d| d.deliver_at) {
+                            Some(at) if at <= now => break queue.pending.pop().unwrap(),
+                            Some(at) => queue = available.wait_timeout(queue, at - now).unwrap().0,
+                            None => queue = available.wait(queue).unwrap(),
+                        }
+                    };
+                    drop(queue);
+                    if !due.inbox.send(due.message) {
+                        log!("Failed to deliver delayed message to host {} - inbox is closed", due.to)
+                    }
+                }
+            });
+            DelayLine { queue }
+        }
+
+        fn schedule(&self, deliver_at: Instant, to: u16, inbox: InboxSender, message: Message) {
+            let (lock, available) = &*self.queue;
+            let mut queue = lock.lock().unwrap();
+            let seq = queue.next_seq;
+            queue.next_seq += 1;
+            queue.pending.push(Delayed { deliver_at, seq, to, inbox, message });
+            available.notify_one();
+        }
+    }
+
+    impl Drop for DelayLine {
+        fn drop(&mut self) {
+            let (lock, available) = &*self.queue;
+            lock.lock().unwrap().stopped = true;
+            available.notify_one();
+        }
+    }
+
+    /// Counters and negotiated compression of the messages between hosts, shared with the routes which
+    /// deliver them outside the lock of the registry.
+    #[derive(Default)]
+    struct Wire {
+        sent: AtomicU64,
+        throttled: AtomicU64,
+        saved: AtomicU64,
+        /// Pairs of a host and a peer the host received a message from which announced it reads compressed messages.
+        accepting: Mutex<HashSet<(u16, u16)>>,
+    }
+
+    impl Wire {
+        fn record_sent(&self, size: usize) {
+            if size > 0 {
+                self.sent.fetch_add(1, Ordering::Relaxed);
+                metrics().messages_sent.inc();
+                metrics().message_size_bytes.observe(size as u64);
+            }
+        }
+    }
+
+    /// The way of a message from one host to another, looked up under the lock of the registry. Sending on
+    /// it encodes, compresses and decodes the message without that lock, so large messages don't hold up
+    /// the other nodes.
+    pub struct Route {
+        from: u16,
+        to: u16,
+        inbox: Option<InboxSender>,
+        compression: Option<Compression>,
+        reads_compressed: bool,
+        delay: Option<(Duration, Arc<DelayLine>)>,
+        rate_limiter: Option<Arc<Mutex<RateLimiter>>>,
+        wire: Arc<Wire>,
+    }
+
+    impl Route {
+        /// Delivers the message to the inbox of the receiving host through its frame, unless the sender is
+        /// over its budget or the receiver can't read the frame.
+        pub fn send(self, message: Message) {
+            let frame = self.frame(&message);
+            let size = frame.as_ref().map_or(0, Vec::len);
+            if !self.admit(size, &message) {
+                return;
+            }
+            let message = match frame {
+                Some(frame) => match self.receive(&frame) {
+                    Some(message) => message,
+                    None => return,
+                },
+                None => message,
+            };
+            let inbox = match self.inbox {
+                Some(inbox) => inbox,
+                None => return,
+            };
+            self.wire.record_sent(size);
+            match self.delay {
+                Some((delay, line)) => line.schedule(Instant::now() + delay, self.to, inbox, message),
+                None => {
+                    if !inbox.send(message) {
+                        log!("Failed to send message to host {} - inbox is closed", self.to)
+                    }
+                }
+            }
+        }
+
+        /// The frame the sender puts on the wire for a network message, compressed if the peer announced
+        /// that it reads compressed messages. Local control messages have none.
+        fn frame(&self, message: &Message) -> Option<Vec<u8>> {
+            if message.priority() == MessagePriority::Control {
+                return None;
+            }
+            let encoded = message.encode();
+            let compression = self.compression.as_ref();
+            let peer_accepts = compression.is_some() && self.wire.accepting.lock().unwrap().contains(&(self.from, self.to));
+            let frame = encode_frame(&encoded, compression, peer_accepts);
+            if frame.len() < encoded.len() {
+                let saved = (encoded.len() - frame.len()) as u64;
+                self.wire.saved.fetch_add(saved, Ordering::Relaxed);
+                metrics().compression_saved_bytes.add(saved);
+            }
+            Some(frame)
+        }
+
+        /// Reads the message from a frame the way the receiving host does, `None` if it can't. A host without
+        /// compression stands for a node of an older version, which ignores the flags but can't read compressed
+        /// messages. A host with compression compresses the messages it sends back if the frame announces
+        /// that its sender reads compressed messages.
+        fn receive(&self, frame: &[u8]) -> Option<Message> {
+            let (from, to) = (self.from, self.to);
+            if !self.reads_compressed && frame.first().is_some_and(|kind| kind & FLAG_COMPRESSED != 0) {
+                log!("Host {} can't read the compressed message from host {}", to, from);
+                return None;
+            }
+            if self.reads_compressed {
+                let mut accepting = self.wire.accepting.lock().unwrap();
+                if accepts_compression(frame) {
+                    accepting.insert((to, from));
+                } else {
+                    accepting.remove(&(to, from));
+                }
+            }
+            let decoded = decode_frame(frame)
+                .map_err(|err| err.0)
+                .and_then(|message| Message::decode(&message).map_err(|err| err.0));
+            match decoded {
+                Ok(message) => Some(message),
+                Err(err) => {
+                    log!("Host {} failed to read the message from host {} - {}", to, from, err);
+                    None
+                }
+            }
+        }
+
+        fn admit(&self, size: usize, message: &Message) -> bool {
+            let admitted = match &self.rate_limiter {
+                Some(limiter) => limiter.lock().unwrap().admit(self.from, size, message.priority(), Instant::now()),
+                None => true,
+            };
+            if !admitted {
+                self.wire.throttled.fetch_add(1, Ordering::Relaxed);
+                metrics().messages_throttled.inc();
+            }
+            admitted
+        }
     }
 
     pub struct ConnectionFactory {
-        connection: HashMap<u16, Sender<Message>>,
+        connection: HashMap<u16, InboxSender>,
+        capacity: usize,
+        policy: DropPolicy,
+        partitions: HashMap<u16, usize>,
+        latency: Option<(LatencyTopology, Arc<DelayLine>)>,
+        rate_limiter: Option<Arc<Mutex<RateLimiter>>>,
+        compression: HashMap<u16, Compression>,
+        wire: Arc<Wire>,
+    }
+
+    impl Default for ConnectionFactory {
+        fn default() -> Self {
+            ConnectionFactory::new()
+        }
     }
 
     impl ConnectionFactory {
         pub fn new() -> ConnectionFactory {
+            ConnectionFactory::with_capacity(DEFAULT_INBOX_CAPACITY, DropPolicy::PrioritizeAcks)
+        }
+
+        pub fn with_capacity(capacity: usize, policy: DropPolicy) -> ConnectionFactory {
             ConnectionFactory {
-                connection: HashMap::new()
+                connection: HashMap::new(),
+                capacity,
+                policy,
+                partitions: HashMap::new(),
+                latency: None,
+                rate_limiter: None,
+                compression: HashMap::new(),
+                wire: Arc::new(Wire::default()),
             }
         }
 
-        pub fn get_connection_for(&self, host: u16) -> Option<&Sender<Message>> {
+        /// Delays the messages sent between hosts by the latencies of the topology, instead of delivering
+        /// them right away. Messages sent with `send_to` aren't delayed.
+        pub fn set_latency(&mut self, topology: LatencyTopology) {
+            self.latency = match self.latency.take() {
+                Some((_, line)) => Some((topology, line)),
+                None => Some((topology, Arc::new(DelayLine::start()))),
+            };
+        }
+
+        pub fn latency(&self) -> Option<&LatencyTopology> {
+            self.latency.as_ref().map(|(topology, _)| topology)
+        }
+
+        /// Limits the messages every host sends with `send_from`, messages over the budget of the sender are dropped.
+        pub fn set_rate_limit(&mut self, limit: RateLimit) {
+            self.rate_limiter = Some(Arc::new(Mutex::new(RateLimiter::new(limit))));
+        }
+
+        pub fn rate_limit(&self) -> Option<RateLimit> {
+            self.rate_limiter.as_ref().map(|l| *l.lock().unwrap().limit())
+        }
+
+        /// Number of messages dropped because their sender ran out of its budget.
+        pub fn throttled_messages(&self) -> u64 {
+            self.wire.throttled.load(Ordering::Relaxed)
+        }
+
+        /// Makes the host compress the large messages it sends with `send_from` to the peers which announced that
+        /// they read compressed messages. Hosts without compression stand for nodes of an older version.
+        pub fn set_compression(&mut self, host: u16, compression: Compression) {
+            self.compression.insert(host, compression);
+        }
+
+        /// Makes the host send and read uncompressed messages only, like a node of an older version.
+        pub fn disable_compression(&mut self, host: u16) {
+            self.compression.remove(&host);
+            self.forget_compression(host);
+        }
+
+        pub fn compression(&self, host: u16) -> Option<&Compression> {
+            self.compression.get(&host)
+        }
+
+        fn forget_compression(&self, host: u16) {
+            self.wire.accepting.lock().unwrap().retain(|(a, b)| *a != host && *b != host);
+        }
+
+        /// Number of bytes compression saved so far.
+        pub fn saved_bytes(&self) -> u64 {
+            self.wire.saved.load(Ordering::Relaxed)
+        }
+
+        pub fn get_connection_for(&self, host: u16) -> Option<&InboxSender> {
             self.connection.get(&host)
         }
+
+        pub fn dropped_messages(&self, host: u16) -> u64 {
+            self.connection.get(&host).map_or(0, |c| c.dropped())
+        }
+
+        /// Number of network messages sent through this factory.
+        pub fn sent_messages(&self) -> u64 {
+            self.wire.sent.load(Ordering::Relaxed)
+        }
+
+        pub fn total_dropped_messages(&self) -> u64 {
+            self.connection.values().map(|c| c.dropped()).sum()
+        }
+
+        /// Hosts can reach each other unless both are in partition groups and the groups are different.
+        pub fn can_reach(&self, from: u16, to: u16) -> bool {
+            match (self.partitions.get(&from), self.partitions.get(&to)) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+        }
     }
 
     impl ConnectionRegistry for ConnectionFactory {
         fn send_to(&self, host: u16, message: Message) {
-            match self.connection.get(&host) {
-                Some(c) => {
-                    c.send(message).unwrap_or_else(|err| println!("Failed to send message from host {} - {:?}", host, err))
+            if let Some(c) = self.connection.get(&host) {
+                self.wire.record_sent(message.encoded_len());
+                if !c.send(message) {
+                    log!("Failed to send message to host {} - inbox is closed", host)
                 }
-                _ => {}
             }
         }
 
-        fn add_connection(&mut self, host: u16, connection: Sender<Message>) {
+        fn route(&self, from: u16, to: u16) -> Option<Route> {
+            if !self.can_reach(from, to) {
+                return None;
+            }
+            let inbox = self.connection.get(&to).cloned();
+            let delay = match &self.latency {
+                Some((topology, line)) if inbox.is_some() => Some((topology.delay(from, to, &mut thread_rng()), Arc::clone(line))),
+                _ => None,
+            };
+            Some(Route {
+                from,
+                to,
+                inbox,
+                compression: self.compression.get(&from).copied(),
+                reads_compressed: self.compression.contains_key(&to),
+                delay,
+                rate_limiter: self.rate_limiter.clone(),
+                wire: Arc::clone(&self.wire),
+            })
+        }
+
+        fn partition(&mut self, groups: Vec<Vec<u16>>) {
+            self.partitions = groups.iter()
+                .enumerate()
+                .flat_map(|(i, group)| group.iter().map(move |host| (*host, i)))
+                .collect();
+        }
+
+        fn heal(&mut self) {
+            self.partitions.clear();
+        }
+
+        /// Registers the inbox of a host. The peers forget what they learnt about the compression of the host
+        /// before, it may have restarted as another version.
+        fn add_connection(&mut self, host: u16, connection: InboxSender) {
+            self.forget_compression(host);
             self.connection.insert(host, connection);
         }
 
         fn remove_connection(&mut self, host: u16) {
+            self.forget_compression(host);
             self.connection.remove(&host);
         }
+
+        fn inbox(&self) -> (InboxSender, InboxReceiver) {
+            inbox(self.capacity, self.policy)
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,22 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Silences the diagnostic output of nodes, e.g. while the dashboard owns the terminal.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Prints a diagnostic line unless the output has been silenced with `set_quiet`.
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        if !$crate::console::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,157 @@
+pub mod swim_node {
+    use std::collections::BTreeMap;
+    use std::fmt::{Display, Formatter};
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::Receiver;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::connection::swim_node::ConnectionFactory;
+    use crate::event::swim_node::NodeEvent;
+    use crate::log;
+    use crate::member_node::swim_node::{MemberNode, MemberNodeHandle, MemberNodeState, NodeConfig};
+    use crate::network_router::{ClusterInsight, DefaultNodeFactory, DefaultNodeRequestRouter, FaultInjection, NodeRequestRouter};
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(1);
+    const VIEW_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// The change injected into a formed cluster.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum ClusterEvent {
+        /// A new node joins through the first node of the cluster.
+        Join,
+        /// The last node of the cluster fails.
+        Failure,
+    }
+
+    pub struct ConvergenceConfig {
+        pub cluster_size: u16,
+        pub node_config: NodeConfig,
+        /// How long to wait for the cluster to form and then for the event to be known by all nodes.
+        pub timeout: Duration,
+    }
+
+    pub struct ConvergenceReport {
+        pub event: ClusterEvent,
+        pub cluster_size: u16,
+        /// Time it took each node to learn about the event, `None` if it didn't within the timeout.
+        pub time_to_know: BTreeMap<u16, Option<Duration>>,
+        /// Number of network messages sent between the injection of the event and the convergence.
+        pub messages: u64,
+    }
+
+    impl ConvergenceReport {
+        pub fn informed(&self) -> usize {
+            self.time_to_know.values().filter(|t| t.is_some()).count()
+        }
+
+        pub fn is_converged(&self) -> bool {
+            self.informed() == self.time_to_know.len()
+        }
+
+        /// Time by which the given percentile of nodes knew about the event, using the nearest-rank method.
+        /// Returns `None` if that many nodes never learned about it.
+        pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+            let mut times: Vec<Duration> = self.time_to_know.values().filter_map(|t| *t).collect();
+            times.sort();
+            let total = self.time_to_know.len();
+            if total == 0 {
+                return None;
+            }
+            let rank = ((percentile / 100.0) * total as f64).ceil().max(1.0) as usize;
+            times.get(rank - 1).cloned()
+        }
+    }
+
+    impl Display for ConvergenceReport {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            let format = |d: Option<Duration>| d.map_or(String::from("n/a"), |d| format!("{:?}", d));
+            write!(f, "{:?} in cluster of {}: {}/{} nodes informed, p50 {}, p90 {}, p99 {}, max {}, {} messages",
+                   self.event, self.cluster_size, self.informed(), self.time_to_know.len(),
+                   format(self.percentile(50.0)), format(self.percentile(90.0)), format(self.percentile(99.0)),
+                   format(self.percentile(100.0)), self.messages)
+        }
+    }
+
+    /// Forms a cluster of `config.cluster_size` nodes on a fresh in-memory network, injects the event
+    /// and records how long it takes for every node to know about it.
+    pub fn measure_convergence(config: &ConvergenceConfig, event: ClusterEvent) -> ConvergenceReport {
+        let connection_factory = Arc::new(Mutex::new(ConnectionFactory::new()));
+        let node_factory = DefaultNodeFactory::with_config(config.node_config.clone());
+        let mut router = DefaultNodeRequestRouter::new(Box::new(node_factory), connection_factory.clone());
+        router.start();
+        for host in 2..=config.cluster_size {
+            router.send(host, 1);
+        }
+        wait_for_full_view(&router, config.timeout);
+
+        let (subject, observers) = match event {
+            ClusterEvent::Join => (config.cluster_size + 1, router.hosts()),
+            ClusterEvent::Failure => (config.cluster_size, router.hosts().into_iter().filter(|h| *h != config.cluster_size).collect()),
+        };
+        let subscriptions: Vec<(u16, Receiver<NodeEvent>)> = observers.iter()
+            .map(|host| (*host, router.node(*host).unwrap().subscribe()))
+            .collect();
+
+        let messages_before = connection_factory.lock().unwrap().sent_messages();
+        let started = Instant::now();
+        match event {
+            ClusterEvent::Join => router.send(subject, 1),
+            ClusterEvent::Failure => router.fail(subject),
+        }
+
+        let mut time_to_know: BTreeMap<u16, Option<Duration>> = observers.iter().map(|h| (*h, None)).collect();
+        while time_to_know.values().any(|t| t.is_none()) && started.elapsed() < config.timeout {
+            for (host, receiver) in subscriptions.iter() {
+                if time_to_know[host].is_some() {
+                    continue;
+                }
+                while let Ok(e) = receiver.try_recv() {
+                    if knows_about(&e, event, subject) {
+                        time_to_know.insert(*host, Some(started.elapsed()));
+                        break;
+                    }
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        let messages = connection_factory.lock().unwrap().sent_messages() - messages_before;
+        router.shut_down();
+
+        ConvergenceReport {
+            event,
+            cluster_size: config.cluster_size,
+            time_to_know,
+            messages,
+        }
+    }
+
+    fn knows_about(e: &NodeEvent, event: ClusterEvent, subject: u16) -> bool {
+        match (event, e) {
+            (ClusterEvent::Join, NodeEvent::MemberStateChanged(host, MemberNodeState::Alive)) => *host == subject,
+            (ClusterEvent::Failure, NodeEvent::MemberStateChanged(host, MemberNodeState::Failed)) => *host == subject,
+            (ClusterEvent::Failure, NodeEvent::MemberRemoved(host)) => *host == subject,
+            _ => false,
+        }
+    }
+
+    fn wait_for_full_view(router: &DefaultNodeRequestRouter<MemberNodeHandle>, timeout: Duration) {
+        let hosts = router.hosts();
+        let started = Instant::now();
+        while started.elapsed() < timeout {
+            let formed = hosts.iter().all(|host| {
+                let details = match router.node(*host).and_then(|node| node.details()) {
+                    Some(details) => details,
+                    None => return false,
+                };
+                hosts.iter()
+                    .filter(|h| *h != host)
+                    .all(|h| details.members().get_state_for(*h) == Some(&MemberNodeState::Alive))
+            });
+            if formed {
+                return;
+            }
+            thread::sleep(VIEW_POLL_INTERVAL);
+        }
+        log!("Cluster of {} nodes didn't form within {:?}", hosts.len(), timeout);
+    }
+}
@@ -0,0 +1,104 @@
+pub mod swim_node {
+    use std::time::Duration;
+    use rand::{Rng, thread_rng};
+
+    pub const DIMENSIONS: usize = 8;
+    /// Error of a coordinate which wasn't updated yet, and the largest error a coordinate can have.
+    const MAX_ERROR: f64 = 1.5;
+    /// How fast the error estimate follows new samples.
+    const ERROR_GAIN: f64 = 0.25;
+    /// How far a coordinate moves towards the position explaining a new sample.
+    const MOVE_GAIN: f64 = 0.25;
+    /// Smallest height in seconds, models the access link every packet goes through.
+    const MIN_HEIGHT: f64 = 10.0e-6;
+    const ZERO: f64 = 1.0e-6;
+
+    /// Vivaldi network coordinate of a node. The distance between two coordinates estimates the round-trip
+    /// time between the nodes in seconds: a Euclidean part for the core network plus the height of both nodes
+    /// for their access links. The error is the relative accuracy of the coordinate, from 0 (exact) to 1.5.
+    #[derive(Clone, PartialEq, Debug)]
+    pub struct Coordinate {
+        pub vec: [f64; DIMENSIONS],
+        pub height: f64,
+        pub error: f64,
+    }
+
+    impl Default for Coordinate {
+        fn default() -> Self {
+            Coordinate::new()
+        }
+    }
+
+    impl Coordinate {
+        /// A coordinate at the origin with the largest error, so the first samples move it quickly.
+        pub fn new() -> Coordinate {
+            Coordinate { vec: [0.0; DIMENSIONS], height: MIN_HEIGHT, error: MAX_ERROR }
+        }
+
+        /// Estimated round-trip time to the node at the other coordinate, `None` if either coordinate isn't valid.
+        pub fn distance_to(&self, other: &Coordinate) -> Option<Duration> {
+            Duration::try_from_secs_f64(self.raw_distance(other)).ok()
+        }
+
+        /// Moves the coordinate to better explain the round-trip time measured to the node at the other
+        /// coordinate. Samples from a node with a less accurate coordinate move it less.
+        pub fn update(&mut self, other: &Coordinate, rtt: Duration) {
+            if !other.is_valid() {
+                return;
+            }
+            let rtt = rtt.as_secs_f64().max(ZERO);
+            let distance = self.raw_distance(other);
+            let weight = self.error / (self.error + other.error).max(ZERO);
+            let wrongness = (distance - rtt).abs() / rtt;
+            self.error = (ERROR_GAIN * weight * wrongness + self.error * (1.0 - ERROR_GAIN * weight)).min(MAX_ERROR);
+
+            let force = MOVE_GAIN * weight * (rtt - distance);
+            let (unit, magnitude) = unit_vector(&self.vec, &other.vec);
+            for (v, u) in self.vec.iter_mut().zip(unit.iter()) {
+                *v += u * force;
+            }
+            // The heights take their share of the estimated distance. Scaling by the Euclidean part alone makes
+            // them explode while the coordinates are still close together, and the system settles in a fold.
+            if magnitude > ZERO {
+                self.height = ((self.height + other.height) * force / distance.max(ZERO) + self.height).max(MIN_HEIGHT);
+            }
+        }
+
+        /// False if a component isn't a finite number, or the height or error is negative, e.g. in a coordinate
+        /// received from a broken node.
+        pub fn is_valid(&self) -> bool {
+            self.vec.iter().all(|v| v.is_finite())
+                && self.height.is_finite() && self.height >= 0.0
+                && self.error.is_finite() && self.error >= 0.0
+        }
+
+        fn raw_distance(&self, other: &Coordinate) -> f64 {
+            let euclidean = self.vec.iter().zip(other.vec.iter())
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<f64>()
+                .sqrt();
+            euclidean + self.height + other.height
+        }
+    }
+
+    /// Unit vector pointing from `to` towards `from` and the distance between them. Nodes at the same
+    /// position are pushed apart in a random direction.
+    fn unit_vector(from: &[f64; DIMENSIONS], to: &[f64; DIMENSIONS]) -> ([f64; DIMENSIONS], f64) {
+        let mut diff = [0.0; DIMENSIONS];
+        for (d, (a, b)) in diff.iter_mut().zip(from.iter().zip(to.iter())) {
+            *d = a - b;
+        }
+        let magnitude = norm(&diff);
+        if magnitude > ZERO {
+            return (diff.map(|d| d / magnitude), magnitude);
+        }
+        let mut rng = thread_rng();
+        let random = [0.0; DIMENSIONS].map(|_: f64| rng.gen::<f64>() - 0.5);
+        let random_magnitude = norm(&random).max(ZERO);
+        (random.map(|d| d / random_magnitude), 0.0)
+    }
+
+    fn norm(vec: &[f64; DIMENSIONS]) -> f64 {
+        vec.iter().map(|v| v * v).sum::<f64>().sqrt()
+    }
+}
@@ -0,0 +1,144 @@
+pub mod swim_node {
+    use std::collections::{BTreeMap, BTreeSet, VecDeque};
+    use std::fmt::Write;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Receiver;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::event::swim_node::NodeEvent;
+    use crate::member_node::swim_node::MemberNodeState;
+    use crate::metrics::swim_node::metrics;
+
+    const EVENT_LOG_SIZE: usize = 15;
+    const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+    const RESET: &str = "\x1b[0m";
+
+    /// Membership views of all nodes as seen through their events, rendered as a nodes × members matrix.
+    pub struct Dashboard {
+        views: BTreeMap<u16, BTreeMap<u16, MemberNodeState>>,
+        event_log: VecDeque<String>,
+        started: Instant,
+        messages_per_second: f64,
+        last_sample: Option<(Instant, u64)>,
+    }
+
+    impl Default for Dashboard {
+        fn default() -> Self {
+            Dashboard::new()
+        }
+    }
+
+    impl Dashboard {
+        pub fn new() -> Dashboard {
+            Dashboard {
+                views: BTreeMap::new(),
+                event_log: VecDeque::new(),
+                started: Instant::now(),
+                messages_per_second: 0.0,
+                last_sample: None,
+            }
+        }
+
+        pub fn apply(&mut self, host: u16, event: &NodeEvent) {
+            let view = self.views.entry(host).or_default();
+            let line = match event {
+                NodeEvent::MemberStateChanged(member, state) => {
+                    view.insert(*member, *state);
+                    format!("node {} sees {} {}", host, member, state)
+                }
+                NodeEvent::MemberRemoved(member) => {
+                    view.remove(member);
+                    format!("node {} removed {}", host, member)
+                }
+                NodeEvent::MemberTagsChanged(member, tags) => format!("node {} sees {} tagged {:?}", host, member, tags),
+                NodeEvent::UserEvent(e) =>
+                    format!("node {} got event {}@{} from {} ({} bytes)", host, e.name, e.ltime, e.origin, e.payload.len()),
+                NodeEvent::LeaderChanged(l) => match l.leader {
+                    Some(leader) => format!("node {} follows leader {} in term {}", host, leader, l.term),
+                    None => format!("node {} has no leader", host),
+                },
+                NodeEvent::PartitionSuspected(p) =>
+                    format!("node {} suspects a partition from {:?} in zones {:?}", host, p.members, p.zones),
+            };
+            self.log(line);
+        }
+
+        /// Updates the message rate from the total number of messages sent so far.
+        pub fn sample_messages(&mut self, total_sent: u64) {
+            let now = Instant::now();
+            if let Some((at, sent)) = self.last_sample {
+                let elapsed = now.duration_since(at).as_secs_f64();
+                if elapsed > 0.0 {
+                    self.messages_per_second = total_sent.saturating_sub(sent) as f64 / elapsed;
+                }
+            }
+            self.last_sample = Some((now, total_sent));
+        }
+
+        fn log(&mut self, line: String) {
+            let elapsed = self.started.elapsed().as_secs_f64();
+            self.event_log.push_back(format!("[{:>7.1}s] {}", elapsed, line));
+            if self.event_log.len() > EVENT_LOG_SIZE {
+                self.event_log.pop_front();
+            }
+        }
+
+        pub fn render(&self, color: bool) -> String {
+            let mut hosts: BTreeSet<u16> = self.views.keys().cloned().collect();
+            for view in self.views.values() {
+                hosts.extend(view.keys());
+            }
+            let width = hosts.iter().map(|h| h.to_string().len()).max().unwrap_or(1) + 2;
+
+            let mut out = String::new();
+            let _ = writeln!(out, "SWIM cluster: {} nodes, {:.1} msg/s, uptime {:.1}s\n",
+                             self.views.len(), self.messages_per_second, self.started.elapsed().as_secs_f64());
+            let _ = write!(out, "{:>w$}", "", w = width + 5);
+            for host in hosts.iter() {
+                let _ = write!(out, "{:>w$}", host, w = width);
+            }
+            out.push('\n');
+            for (host, view) in self.views.iter() {
+                let _ = write!(out, "node {:<w$}", host, w = width);
+                for member in hosts.iter() {
+                    let (symbol, code) = if member == host {
+                        ("-", None)
+                    } else {
+                        match view.get(member) {
+                            Some(MemberNodeState::Alive) => ("A", Some("\x1b[32m")),
+                            Some(MemberNodeState::Suspected) => ("S", Some("\x1b[33m")),
+                            Some(MemberNodeState::Failed) => ("F", Some("\x1b[31m")),
+                            Some(MemberNodeState::Left) => ("L", None),
+                            Some(MemberNodeState::Degraded) => ("D", Some("\x1b[35m")),
+                            None => (".", None),
+                        }
+                    };
+                    match code {
+                        Some(code) if color => { let _ = write!(out, "{}{:>w$}{}", code, symbol, RESET, w = width); }
+                        _ => { let _ = write!(out, "{:>w$}", symbol, w = width); }
+                    }
+                }
+                out.push('\n');
+            }
+            let _ = writeln!(out, "\nA alive, D degraded, S suspected, F failed, L left, . unknown\n\nEvents:");
+            for line in self.event_log.iter() {
+                let _ = writeln!(out, "{}", line);
+            }
+            out
+        }
+    }
+
+    /// Redraws the dashboard on the terminal every `refresh` until `stop` is set.
+    pub fn run_dashboard(events: Receiver<(u16, NodeEvent)>, refresh: Duration, stop: Arc<AtomicBool>) {
+        let mut dashboard = Dashboard::new();
+        while !stop.load(Ordering::Relaxed) {
+            while let Ok((host, event)) = events.try_recv() {
+                dashboard.apply(host, &event);
+            }
+            dashboard.sample_messages(metrics().messages_sent.get());
+            print!("{}{}", CLEAR_SCREEN, dashboard.render(true));
+            thread::sleep(refresh);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+pub mod swim_node {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+    use crate::member_node::swim_node::{MemberNodeState, MemberNodesRegistry};
+
+    /// Name of the user event a node broadcasts with its fencing term when it becomes the leader.
+    pub const LEADER_EVENT: &str = "swim-leader";
+
+    #[derive(Clone, Debug)]
+    pub struct ElectionConfig {
+        /// How long a member has to stay alive before it can become the leader.
+        pub stability: Duration,
+        /// How long a leader which failed may still believe it leads, nobody takes over before it runs out.
+        pub lease: Duration,
+    }
+
+    impl Default for ElectionConfig {
+        fn default() -> Self {
+            ElectionConfig {
+                stability: Duration::from_secs(3),
+                lease: Duration::from_secs(5),
+            }
+        }
+    }
+
+    /// The leader a node follows and the fencing term of its leadership. Terms only grow, so a resource
+    /// can reject the writes of a leader which was replaced in the meantime. The low 16 bits of a term
+    /// hold the host of the leader, so two leaders never claim the same term.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct Leadership {
+        pub leader: Option<u16>,
+        pub term: u64,
+    }
+
+    /// Leader election based on the membership view of a node. The leader is the member with the lowest
+    /// host among the ones alive for at least `stability`, provided the node sees a majority of the members
+    /// it knows of which haven't left, failed ones included until their tombstones are reaped. A suspected leader keeps its leadership, and a failed one is replaced only once
+    /// its lease has run out, so a SWIM false positive doesn't lead to two leaders at once. A node without
+    /// a leader, e.g. one which just started or rejoined after a partition, waits for a lease as well before
+    /// it elects one.
+    pub struct Election {
+        config: ElectionConfig,
+        host: u16,
+        started: Instant,
+        leader: Option<u16>,
+        term: u64,
+        alive_since: HashMap<u16, Instant>,
+        leader_lost_at: Option<Instant>,
+    }
+
+    impl Election {
+        pub fn new(host: u16, config: ElectionConfig, now: Instant) -> Election {
+            Election {
+                config,
+                host,
+                started: now,
+                leader: None,
+                term: 0,
+                alive_since: HashMap::new(),
+                leader_lost_at: None,
+            }
+        }
+
+        pub fn leadership(&self) -> Leadership {
+            Leadership { leader: self.leader, term: self.term }
+        }
+
+        /// Adopts the term announced by a leader. A leader which learns of a higher term claims
+        /// the next one, so its writes aren't fenced off. Returns true if the leadership has changed.
+        pub fn observe_term(&mut self, term: u64) -> bool {
+            if term <= self.term {
+                return false;
+            }
+            self.term = if self.is_leader() { self.next_term(term) } else { term };
+            true
+        }
+
+        /// Re-evaluates the leader from the current membership. Returns true if the leadership has changed.
+        pub fn update(&mut self, members: &MemberNodesRegistry, now: Instant) -> bool {
+            let before = self.leadership();
+            let (mut reachable, mut known) = (1, 1);
+            let own = self.host;
+            for host in members.hosts().into_iter().filter(|h| *h != own) {
+                match members.get_state_for(host) {
+                    Some(MemberNodeState::Alive) => {
+                        reachable += 1;
+                        known += 1;
+                        self.alive_since.entry(host).or_insert(now);
+                    }
+                    Some(MemberNodeState::Suspected) | Some(MemberNodeState::Degraded) => {
+                        reachable += 1;
+                        known += 1;
+                        self.alive_since.remove(&host);
+                    }
+                    Some(MemberNodeState::Left) => {
+                        self.alive_since.remove(&host);
+                    }
+                    _ => {
+                        known += 1;
+                        self.alive_since.remove(&host);
+                    }
+                }
+            }
+            self.alive_since.retain(|host, _| members.get_state_for(*host).is_some());
+
+            if reachable * 2 <= known {
+                self.leader = None;
+                self.leader_lost_at = None;
+                return self.leadership() != before;
+            }
+
+            let stable = |since: Instant| now.duration_since(since) >= self.config.stability;
+            let candidate = self.alive_since.iter()
+                .filter(|(_, since)| stable(**since))
+                .map(|(host, _)| *host)
+                .chain(Some(self.host).filter(|_| stable(self.started)))
+                .min();
+
+            match self.leader {
+                Some(leader) if leader == self.host || members.get_state_for(leader).is_some_and(|s| !s.is_tombstone()) => {
+                    self.leader_lost_at = None;
+                    if candidate.is_some_and(|c| c < leader) {
+                        self.elect(candidate);
+                    }
+                }
+                _ => {
+                    let lost_at = *self.leader_lost_at.get_or_insert(now);
+                    if now.duration_since(lost_at) >= self.config.lease && candidate.is_some() {
+                        self.leader_lost_at = None;
+                        self.elect(candidate);
+                    }
+                }
+            }
+            self.leadership() != before
+        }
+
+        fn elect(&mut self, candidate: Option<u16>) {
+            if candidate.is_some() && candidate != self.leader {
+                self.leader = candidate;
+                if candidate == Some(self.host) {
+                    self.term = self.next_term(self.term);
+                }
+            }
+        }
+
+        fn next_term(&self, after: u64) -> u64 {
+            ((after >> 16) + 1) << 16 | self.host as u64
+        }
+
+        /// True if this node leads, it announces its term whenever the term changes.
+        pub fn is_leader(&self) -> bool {
+            self.leader == Some(self.host)
+        }
+    }
+}
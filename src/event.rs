@@ -0,0 +1,24 @@
+pub mod swim_node {
+    use std::collections::BTreeMap;
+    use crate::election::swim_node::Leadership;
+    use crate::member_node::swim_node::MemberNodeState;
+    use crate::partition::swim_node::PartitionSuspicion;
+    use crate::user_event::swim_node::UserEvent;
+
+    /// Events published by a node to its subscribers.
+    #[derive(Clone, PartialEq, Debug)]
+    pub enum NodeEvent {
+        /// A member was added to the membership list or its state has changed.
+        MemberStateChanged(u16, MemberNodeState),
+        /// A member was removed from the membership list.
+        MemberRemoved(u16),
+        /// A member announced new tags.
+        MemberTagsChanged(u16, BTreeMap<String, String>),
+        /// A user event was broadcast by this node or received from another member for the first time.
+        UserEvent(UserEvent),
+        /// The node follows another leader, or learned the fencing term of its leader.
+        LeaderChanged(Leadership),
+        /// Many members failed at once, the node is likely cut off from them rather than all of them crashed.
+        PartitionSuspected(PartitionSuspicion),
+    }
+}
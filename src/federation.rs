@@ -0,0 +1,90 @@
+pub mod swim_node {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::connection::swim_node::ConnectionRegistry;
+    use crate::member_node::swim_node::{DefaultMemberNode, MemberNodeHandle, MemberNodeState, NodeConfig};
+
+    /// Tag of the WAN members naming the cluster they are the gateway of.
+    pub const CLUSTER_TAG: &str = "cluster";
+
+    /// Round trips between regions are much longer than within a cluster, so WAN members wait longer
+    /// for acks and ask more members to probe a silent gateway before suspecting it.
+    const WAN_PING_INTERVAL: Duration = Duration::from_secs(5);
+    const WAN_INDIRECT_PROBES: usize = 4;
+
+    /// Protocol parameters of a WAN member, which joins the pool through the gateways of the seeds.
+    pub fn wan_config(seeds: Vec<u16>) -> NodeConfig {
+        NodeConfig {
+            ping_interval: WAN_PING_INTERVAL,
+            indirect_probes: WAN_INDIRECT_PROBES,
+            seeds,
+            ..NodeConfig::default()
+        }
+    }
+
+    /// A node of a cluster which is also a member of the WAN pool formed by the gateways of all clusters.
+    /// The two memberships are independent: the LAN instance only knows the members of its cluster and
+    /// the WAN instance only knows the gateways, tagged with the names of their clusters.
+    pub struct Gateway {
+        cluster: String,
+        lan: MemberNodeHandle,
+        wan: MemberNodeHandle,
+    }
+
+    impl Gateway {
+        /// Starts the WAN instance of the cluster node as the given host of the WAN pool.
+        pub fn start(cluster: &str, lan: MemberNodeHandle, wan_host: u16, mut config: NodeConfig,
+                     pool: Arc<Mutex<dyn ConnectionRegistry>>) -> Gateway {
+            config.tags.insert(String::from(CLUSTER_TAG), String::from(cluster));
+            let wan = DefaultMemberNode::start_with_config(wan_host, config, pool);
+            Gateway { cluster: String::from(cluster), lan, wan }
+        }
+
+        pub fn cluster(&self) -> &str {
+            &self.cluster
+        }
+
+        pub fn lan(&self) -> &MemberNodeHandle {
+            &self.lan
+        }
+
+        pub fn wan(&self) -> &MemberNodeHandle {
+            &self.wan
+        }
+
+        /// The gateways of the other clusters known to the WAN instance, with their states. None are known
+        /// once the WAN instance has shut down.
+        pub fn remote_gateways(&self) -> BTreeMap<String, BTreeMap<u16, MemberNodeState>> {
+            let mut clusters: BTreeMap<String, BTreeMap<u16, MemberNodeState>> = BTreeMap::new();
+            let details = match self.wan.details() {
+                Some(details) => details,
+                None => return clusters,
+            };
+            let members = details.members();
+            for host in members.hosts() {
+                let cluster = match members.get_tags_for(host).and_then(|tags| tags.get(CLUSTER_TAG)) {
+                    Some(cluster) if *cluster != self.cluster => cluster,
+                    _ => continue,
+                };
+                if let Some(state) = members.get_state_for(host) {
+                    clusters.entry(cluster.clone()).or_default().insert(host, *state);
+                }
+            }
+            clusters
+        }
+
+        /// The other clusters with at least one alive gateway, degraded gateways still relay requests.
+        pub fn reachable_clusters(&self) -> Vec<String> {
+            self.remote_gateways().into_iter()
+                .filter(|(_, gateways)| gateways.values().any(|s| matches!(s, MemberNodeState::Alive | MemberNodeState::Degraded)))
+                .map(|(cluster, _)| cluster)
+                .collect()
+        }
+
+        /// Leaves the WAN pool. The LAN instance keeps running, it belongs to the router of its cluster.
+        pub fn shut_down(&self) {
+            self.wan.shut_down();
+        }
+    }
+}
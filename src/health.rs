@@ -0,0 +1,139 @@
+pub mod swim_node {
+    use std::fmt::{Debug, Formatter};
+    use std::process::{Child, Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    /// A check run in the node loop, it has to return quickly. The error describes what's broken.
+    pub type CheckFn = Box<dyn FnMut() -> Result<(), String> + Send>;
+
+    pub enum HealthCheck {
+        Closure(CheckFn),
+        /// A local command with its arguments, healthy if it exits with status 0. The node doesn't wait for it:
+        /// a round reports the exit status of the run started in the previous round, and a run still going
+        /// by then is killed and reported as failed.
+        Command(String, Vec<String>),
+    }
+
+    impl Debug for HealthCheck {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                HealthCheck::Closure(_) => write!(f, "Closure"),
+                HealthCheck::Command(program, args) => write!(f, "Command({:?}, {:?})", program, args),
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct HealthConfig {
+        /// How often the registered checks run.
+        pub interval: Duration,
+        /// Number of rounds in a row with a failed check after which the node leaves the cluster,
+        /// it stays degraded if it's `None`.
+        pub leave_after: Option<u32>,
+    }
+
+    impl Default for HealthConfig {
+        fn default() -> Self {
+            HealthConfig {
+                interval: Duration::from_secs(10),
+                leave_after: None,
+            }
+        }
+    }
+
+    /// Outcome of a round of health checks.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub enum HealthStatus {
+        Healthy,
+        /// Names of the failed checks with their errors.
+        Degraded(Vec<(String, String)>),
+        /// The checks failed for `leave_after` rounds in a row.
+        Leave,
+    }
+
+    struct Registered {
+        name: String,
+        check: HealthCheck,
+        running: Option<Child>,
+    }
+
+    impl Registered {
+        fn run(&mut self) -> Result<(), String> {
+            let (program, args) = match &mut self.check {
+                HealthCheck::Closure(check) => return check(),
+                HealthCheck::Command(program, args) => (program, args),
+            };
+            let result = match self.running.take() {
+                Some(mut child) => match child.try_wait() {
+                    Ok(Some(status)) if status.success() => Ok(()),
+                    Ok(Some(status)) => Err(format!("exited with {}", status)),
+                    Ok(None) => {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        Err(String::from("timed out"))
+                    }
+                    Err(err) => Err(err.to_string()),
+                },
+                None => Ok(()),
+            };
+            match Command::new(program.as_str()).args(args.iter()).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn() {
+                Ok(child) => self.running = Some(child),
+                Err(err) => return Err(err.to_string()),
+            }
+            result
+        }
+    }
+
+    impl Drop for Registered {
+        fn drop(&mut self) {
+            if let Some(mut child) = self.running.take() {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+
+    /// The health checks registered on a node, run once per interval from the ticks of the node.
+    pub struct HealthChecks {
+        config: HealthConfig,
+        checks: Vec<Registered>,
+        last_run: Option<Instant>,
+        failed_rounds: u32,
+    }
+
+    impl HealthChecks {
+        pub fn new(config: HealthConfig) -> HealthChecks {
+            HealthChecks { config, checks: Vec::new(), last_run: None, failed_rounds: 0 }
+        }
+
+        /// Adds a check, replacing the check with the same name.
+        pub fn register(&mut self, name: &str, check: HealthCheck) {
+            self.checks.retain(|c| c.name != name);
+            self.checks.push(Registered { name: String::from(name), check, running: None });
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.checks.is_empty()
+        }
+
+        /// Runs the checks if the interval has passed since the last round, `None` if it hasn't.
+        pub fn run(&mut self, now: Instant) -> Option<HealthStatus> {
+            if self.checks.is_empty() || self.last_run.is_some_and(|at| now.duration_since(at) < self.config.interval) {
+                return None;
+            }
+            self.last_run = Some(now);
+            let failed: Vec<(String, String)> = self.checks.iter_mut()
+                .filter_map(|c| c.run().err().map(|err| (c.name.clone(), err)))
+                .collect();
+            if failed.is_empty() {
+                self.failed_rounds = 0;
+                return Some(HealthStatus::Healthy);
+            }
+            self.failed_rounds += 1;
+            if self.config.leave_after.is_some_and(|rounds| self.failed_rounds >= rounds) {
+                return Some(HealthStatus::Leave);
+            }
+            Some(HealthStatus::Degraded(failed))
+        }
+    }
+}
@@ -0,0 +1,96 @@
+pub mod swim_node {
+    use std::collections::HashMap;
+    use std::f64::consts::PI;
+    use std::time::Duration;
+    use rand::Rng;
+
+    /// Distribution of the one-way delay of a message.
+    #[derive(Clone, PartialEq, Debug)]
+    pub enum LatencyDistribution {
+        Constant(Duration),
+        /// Uniformly distributed between the two delays.
+        Uniform(Duration, Duration),
+        /// Normally distributed with the mean and the standard deviation, cut off at zero.
+        Normal(Duration, Duration),
+    }
+
+    impl LatencyDistribution {
+        pub fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+            match self {
+                LatencyDistribution::Constant(delay) => *delay,
+                LatencyDistribution::Uniform(low, high) if low < high => rng.gen_range(*low..*high),
+                LatencyDistribution::Uniform(low, _) => *low,
+                LatencyDistribution::Normal(mean, std_dev) => {
+                    let (u1, u2): (f64, f64) = (1.0 - rng.gen::<f64>(), rng.gen());
+                    let z = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+                    Duration::from_secs_f64((mean.as_secs_f64() + z * std_dev.as_secs_f64()).max(0.0))
+                }
+            }
+        }
+    }
+
+    /// Delays of the simulated network. Hosts are placed in zones, e.g. racks or data centers, and a message
+    /// is delayed by the distribution of the zones of its sender and receiver plus a random jitter.
+    /// Hosts without a zone are each in a zone of their own.
+    #[derive(Clone, Debug)]
+    pub struct LatencyTopology {
+        zones: HashMap<u16, String>,
+        intra_zone: LatencyDistribution,
+        inter_zone: LatencyDistribution,
+        links: HashMap<(String, String), LatencyDistribution>,
+        jitter: Duration,
+    }
+
+    impl LatencyTopology {
+        pub fn new(intra_zone: LatencyDistribution, inter_zone: LatencyDistribution) -> LatencyTopology {
+            LatencyTopology {
+                zones: HashMap::new(),
+                intra_zone,
+                inter_zone,
+                links: HashMap::new(),
+                jitter: Duration::ZERO,
+            }
+        }
+
+        pub fn place(&mut self, host: u16, zone: &str) {
+            self.zones.insert(host, String::from(zone));
+        }
+
+        /// Overrides the inter-zone distribution between two zones, in both directions.
+        pub fn link(&mut self, zone: &str, other: &str, latency: LatencyDistribution) {
+            self.links.insert(link_key(zone, other), latency);
+        }
+
+        /// Upper bound of the uniformly distributed delay added to every message.
+        pub fn set_jitter(&mut self, jitter: Duration) {
+            self.jitter = jitter;
+        }
+
+        pub fn zone_of(&self, host: u16) -> Option<&str> {
+            self.zones.get(&host).map(String::as_str)
+        }
+
+        pub fn distribution(&self, from: u16, to: u16) -> &LatencyDistribution {
+            match (self.zone_of(from), self.zone_of(to)) {
+                _ if from == to => &self.intra_zone,
+                (Some(a), Some(b)) if a == b => &self.intra_zone,
+                (Some(a), Some(b)) => self.links.get(&link_key(a, b)).unwrap_or(&self.inter_zone),
+                _ => &self.inter_zone,
+            }
+        }
+
+        /// Samples the one-way delay of a message between the hosts.
+        pub fn delay<R: Rng>(&self, from: u16, to: u16, rng: &mut R) -> Duration {
+            let jitter = if self.jitter.is_zero() { Duration::ZERO } else { rng.gen_range(Duration::ZERO..self.jitter) };
+            self.distribution(from, to).sample(rng) + jitter
+        }
+    }
+
+    fn link_key(zone: &str, other: &str) -> (String, String) {
+        if zone <= other {
+            (String::from(zone), String::from(other))
+        } else {
+            (String::from(other), String::from(zone))
+        }
+    }
+}
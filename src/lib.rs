@@ -1,16 +1,56 @@
+pub mod console;
 pub mod member_node;
 pub mod network_router;
 pub mod connection;
 pub mod message;
+pub mod event;
+pub mod convergence;
+pub mod metrics;
+pub mod scenario;
+pub mod repl;
+pub mod dashboard;
+pub mod snapshot;
+pub mod query;
+pub mod rpc;
+pub mod ring;
+pub mod rendezvous;
+pub mod user_event;
+pub mod election;
+pub mod coordinate;
+pub mod latency;
+pub mod federation;
+pub mod partition;
+pub mod health;
+pub mod rate_limit;
+pub mod compression;
+#[cfg(feature = "async")]
+pub mod async_node;
 mod tests;
 
 use std::sync::{Arc, Mutex};
 use crate::connection::swim_node::ConnectionFactory;
+use crate::latency::swim_node::LatencyTopology;
+use crate::member_node::swim_node::NodeConfig;
 use crate::network_router::{DefaultNodeRequestRouter, NodeRequestRouter, DefaultNodeFactory};
 
 pub fn run_network() -> Box<dyn NodeRequestRouter> {
-    let node_factory = DefaultNodeFactory {};
-    let mut router = DefaultNodeRequestRouter::new(Box::<DefaultNodeFactory>::new(node_factory), Arc::new(Mutex::new(ConnectionFactory::new())));
+    run_network_with_config(NodeConfig::default())
+}
+
+pub fn run_network_with_config(config: NodeConfig) -> Box<dyn NodeRequestRouter> {
+    start_network(config, ConnectionFactory::new())
+}
+
+/// Runs a network whose messages between nodes are delayed by the latencies of the topology.
+pub fn run_network_with_latency(config: NodeConfig, topology: LatencyTopology) -> Box<dyn NodeRequestRouter> {
+    let mut connection_factory = ConnectionFactory::new();
+    connection_factory.set_latency(topology);
+    start_network(config, connection_factory)
+}
+
+fn start_network(config: NodeConfig, connection_factory: ConnectionFactory) -> Box<dyn NodeRequestRouter> {
+    let node_factory = DefaultNodeFactory::with_config(config);
+    let mut router = DefaultNodeRequestRouter::new(Box::<DefaultNodeFactory>::new(node_factory), Arc::new(Mutex::new(connection_factory)));
     router.start();
     router
 }
\ No newline at end of file
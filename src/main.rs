@@ -1,14 +1,102 @@
+use std::env;
+use std::io;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::Duration;
-use swim_app::run_network;
+use swim_app::console::set_quiet;
+use swim_app::dashboard::swim_node::run_dashboard;
+use swim_app::member_node::swim_node::NodeConfig;
+use swim_app::metrics::swim_node::serve;
+use swim_app::network_router::NodeRequestRouter;
+use swim_app::repl::swim_node::run_repl;
+use swim_app::scenario::swim_node::Scenario;
+use swim_app::{run_network, run_network_with_config};
+
+const DASHBOARD_REFRESH: Duration = Duration::from_millis(500);
 
 fn main() {
+    if let Ok(address) = env::var("SWIM_METRICS_ADDR") {
+        match serve(address.as_str()) {
+            Ok(bound) => println!("Serving metrics on http://{}/metrics", bound),
+            Err(err) => println!("Failed to serve metrics on {} - {:?}", address, err),
+        }
+    }
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["--repl"] => run_console(),
+        ["--dashboard"] => run_with_dashboard(None),
+        ["--dashboard", path] => run_with_dashboard(Some(load_scenario(path))),
+        [path] => run_scenario(path),
+        _ => run_demo(),
+    }
+}
+
+fn run_console() {
     let mut router = run_network();
+    let stdin = io::stdin();
+    if let Err(err) = run_repl(stdin.lock(), io::stdout(), router.as_mut()) {
+        eprintln!("Console failed - {}", err);
+    }
+    router.shut_down();
+}
+
+fn run_with_dashboard(scenario: Option<Scenario>) {
+    set_quiet(true);
+    let config = scenario.as_ref().map_or(NodeConfig::default(), |s| s.node_config().clone());
+    let mut router = run_network_with_config(config);
+    let events = router.subscribe();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_ref = Arc::clone(&stop);
+    let dashboard = thread::spawn(move || run_dashboard(events, DASHBOARD_REFRESH, stop_ref));
+
+    match scenario {
+        Some(scenario) => {
+            let result = scenario.run(router.as_mut());
+            thread::sleep(DASHBOARD_REFRESH * 4);
+            if let Err(err) = result {
+                println!("\nScenario failed at {}", err);
+            }
+        }
+        None => send_demo_requests(router.as_mut()),
+    }
+    stop.store(true, Ordering::Relaxed);
+    let _ = dashboard.join();
+    router.shut_down();
+}
+
+fn load_scenario(path: &str) -> Scenario {
+    Scenario::load(path).unwrap_or_else(|err| {
+        eprintln!("Invalid scenario {}: {}", path, err);
+        process::exit(2);
+    })
+}
+
+fn run_scenario(path: &str) {
+    let scenario = load_scenario(path);
+    let mut router = run_network_with_config(scenario.node_config().clone());
+    let result = scenario.run(router.as_mut());
+    router.shut_down();
+    if let Err(err) = result {
+        eprintln!("Scenario {} failed at {}", path, err);
+        process::exit(1);
+    }
+    println!("Scenario {} passed", path);
+}
+
+fn run_demo() {
+    let mut router = run_network();
+    send_demo_requests(router.as_mut());
+    router.shut_down();
+}
+
+fn send_demo_requests(router: &mut dyn NodeRequestRouter) {
     router.send(2, 1);
     router.send(3, 1);
     router.send(4, 2);
     router.send(5, 3);
     router.send(6, 1);
     thread::sleep(Duration::from_secs(30));
-    router.shut_down();
 }
@@ -1,135 +1,846 @@
 pub mod swim_node {
-    use crate::connection::swim_node::{ConnectionRegistry};
+    use crate::connection::swim_node::{send_all, ConnectionRegistry, InboxSender};
+    use crate::coordinate::swim_node::Coordinate;
     use std::{thread};
-    use std::collections::{HashMap};
+    use std::collections::{BTreeMap, HashMap};
     use std::fmt::{Display, Formatter};
+    use std::io::ErrorKind;
     use std::ops::{Add, Not};
+    use std::path::PathBuf;
     use std::sync::{Arc, mpsc, Mutex};
-    use std::sync::mpsc::{Receiver, Sender};
-    use std::time::Duration;
+    use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+    use std::time::{Duration, Instant};
     use rand;
     use rand::{Rng, thread_rng};
+    use crate::election::swim_node::{Election, ElectionConfig, Leadership, LEADER_EVENT};
+    use crate::event::swim_node::NodeEvent;
+    use crate::health::swim_node::{HealthCheck, HealthChecks, HealthConfig, HealthStatus};
+    use crate::log;
     use crate::message::swim_node::Message;
+    use crate::metrics::swim_node::metrics;
+    use crate::partition::swim_node::{PartitionConfig, PartitionDetector};
+    use crate::query::swim_node::{Query, QueryEvent, QueryHandler, QueryParams};
+    use crate::rpc::swim_node::{Codec, Envelope, RequestHandler};
+    use crate::snapshot::swim_node::{Snapshot, SnapshotError};
+    use crate::user_event::swim_node::{UserEvent, UserEvents};
 
     const PING_DELAY: u64 = 1;
     const NUMBER_RANDOM_PROBE_NODES: usize = 3;
+    const TOMBSTONE_TIMEOUT: u64 = 60;
+    const RECONNECT_INTERVAL: u64 = 10;
+
+    /// Kind of the request exchanging the full membership lists of two nodes, both sides merge the list of the other.
+    pub const PUSH_PULL: &str = "push-pull";
+
+    /// Protocol parameters of a node.
+    #[derive(Clone, Debug)]
+    pub struct NodeConfig {
+        /// Length of a protocol period, a random member is pinged once per period.
+        pub ping_interval: Duration,
+        /// Number of members asked to probe a member which didn't respond to a ping.
+        pub indirect_probes: usize,
+        /// Directory where the node persists its membership list every protocol period and reads it back
+        /// on startup to rejoin the members it knew.
+        pub snapshot_dir: Option<PathBuf>,
+        /// Nodes to join on startup when there is no usable snapshot.
+        pub seeds: Vec<u16>,
+        /// Tags of the node, matched against the filters of cluster queries.
+        pub tags: BTreeMap<String, String>,
+        /// Enables the leader election among the members, the node takes no part in it if it's `None`.
+        pub election: Option<ElectionConfig>,
+        /// How long failed and left members are kept as tombstones, which reject stale gossip about them,
+        /// before they are removed from the membership list.
+        pub tombstone_timeout: Duration,
+        /// How often the node tries to push-pull with a random failed member, so the two sides of a healed
+        /// partition find each other again.
+        pub reconnect_interval: Duration,
+        /// Enables the detection of partitions, failures are declared as usual if it's `None`.
+        pub partition: Option<PartitionConfig>,
+        /// How often the health checks registered on the node run, and when failing checks make it leave.
+        pub health: HealthConfig,
+    }
+
+    impl Default for NodeConfig {
+        fn default() -> Self {
+            NodeConfig {
+                ping_interval: Duration::from_secs(PING_DELAY),
+                indirect_probes: NUMBER_RANDOM_PROBE_NODES,
+                snapshot_dir: None,
+                seeds: Vec::new(),
+                tags: BTreeMap::new(),
+                election: None,
+                tombstone_timeout: Duration::from_secs(TOMBSTONE_TIMEOUT),
+                reconnect_interval: Duration::from_secs(RECONNECT_INTERVAL),
+                partition: None,
+                health: HealthConfig::default(),
+            }
+        }
+    }
 
     pub trait MemberNode {
         fn host(&self) -> u16;
 
-        fn serialize_host_details(&self) -> MemberNodeDetails;
+        /// The details of the node, or `None` if the node is not running any more.
+        fn serialize_host_details(&self) -> Option<MemberNodeDetails>;
+
+        fn change_state(&self, state: MemberNodeState);
+
+        /// Returns a stream of the events published by the node, starting with its current membership list.
+        fn subscribe(&self) -> Receiver<NodeEvent>;
+
+        /// Disseminates a user event to all members, including the subscribers of this node.
+        fn broadcast_event(&self, name: &str, payload: Vec<u8>);
+
+        fn set_tags(&self, tags: BTreeMap<String, String>);
+
+        /// Registers the handler answering the queries with the given name on this node.
+        fn handle_query(&self, name: &str, handler: QueryHandler);
+
+        /// Registers the handler producing the responses to the requests of the given kind on this node.
+        fn handle_requests(&self, kind: &str, handler: RequestHandler);
+
+        /// Sends a request to the node. The returned stream yields the response body if it arrives within the timeout.
+        fn request(&self, to: u16, kind: &str, body: Vec<u8>, timeout: Duration) -> Receiver<Vec<u8>>;
+
+        /// Sends a query to every known member and this node. The returned stream yields the acks and
+        /// responses of the matching members and ends once the timeout of the query expires.
+        fn query(&self, name: &str, payload: Vec<u8>, params: QueryParams) -> Receiver<QueryEvent>;
+
+        /// The leader this node follows, or `None` if the node doesn't take part in the election.
+        fn leadership(&self) -> Option<Leadership>;
+
+        /// Round-trip time between two members estimated from their network coordinates, `None` if this
+        /// node doesn't know the coordinate of either of them yet.
+        fn estimate_rtt(&self, from: u16, to: u16) -> Option<Duration>;
+
+        /// Registers a check of the service running next to the node. While a check fails, the node
+        /// advertises itself as degraded.
+        fn add_health_check(&self, name: &str, check: HealthCheck);
     }
 
-    pub struct DefaultMemberNode {
-        details: MemberNodeDetails,
+    /// A closure executed by the node loop with exclusive access to the node state.
+    pub type NodeCall = Box<dyn FnOnce(&mut DefaultMemberNode) + Send>;
+
+    /// The inbox of a running node. All state changes of the node go through it.
+    pub trait NodeInbox: Send + Sync {
+        fn deliver(&self, message: Message) -> bool;
+    }
+
+    impl NodeInbox for InboxSender {
+        fn deliver(&self, message: Message) -> bool {
+            self.send(message)
+        }
+    }
+
+    /// A reference to a running node. The node state is owned by a single loop, callers query and
+    /// modify it by sending request messages and waiting for the response.
+    #[derive(Clone)]
+    pub struct MemberNodeHandle {
+        host: u16,
+        inbox: Arc<dyn NodeInbox>,
     }
 
-    impl MemberNode for DefaultMemberNode {
+    impl MemberNode for MemberNodeHandle {
         fn host(&self) -> u16 {
-            self.details.host
+            self.host
+        }
+
+        fn serialize_host_details(&self) -> Option<MemberNodeDetails> {
+            self.details()
+        }
+
+        fn change_state(&self, state: MemberNodeState) {
+            self.call(move |node| node.change_state(state));
+        }
+
+        fn subscribe(&self) -> Receiver<NodeEvent> {
+            let (sender, receiver) = mpsc::channel();
+            self.call(move |node| node.subscribe(sender));
+            receiver
+        }
+
+        fn broadcast_event(&self, name: &str, payload: Vec<u8>) {
+            let name = String::from(name);
+            self.call(move |node| node.broadcast_event(&name, payload));
         }
 
-        fn serialize_host_details(&self) -> MemberNodeDetails {
-            self.details.serialize()
+        fn set_tags(&self, tags: BTreeMap<String, String>) {
+            self.call(move |node| node.details.tags = tags);
+        }
+
+        fn handle_query(&self, name: &str, handler: QueryHandler) {
+            let name = String::from(name);
+            self.call(move |node| node.query_handlers.insert(name, handler));
+        }
+
+        fn handle_requests(&self, kind: &str, handler: RequestHandler) {
+            let kind = String::from(kind);
+            self.call(move |node| node.request_handlers.insert(kind, handler));
+        }
+
+        fn request(&self, to: u16, kind: &str, body: Vec<u8>, timeout: Duration) -> Receiver<Vec<u8>> {
+            let (sender, receiver) = mpsc::channel();
+            let kind = String::from(kind);
+            self.call(move |node| node.request(to, &kind, body, timeout, sender));
+            receiver
+        }
+
+        fn query(&self, name: &str, payload: Vec<u8>, params: QueryParams) -> Receiver<QueryEvent> {
+            let (sender, receiver) = mpsc::channel();
+            let name = String::from(name);
+            self.call(move |node| node.query(&name, payload, params, sender));
+            receiver
+        }
+
+        fn leadership(&self) -> Option<Leadership> {
+            self.call(|node| node.election.as_ref().map(Election::leadership)).flatten()
+        }
+
+        fn estimate_rtt(&self, from: u16, to: u16) -> Option<Duration> {
+            self.call(move |node| node.estimate_rtt(from, to)).flatten()
+        }
+
+        fn add_health_check(&self, name: &str, check: HealthCheck) {
+            let name = String::from(name);
+            self.call(move |node| node.add_health_check(&name, check));
+        }
+    }
+
+    impl MemberNodeHandle {
+        pub fn new(host: u16, inbox: Arc<dyn NodeInbox>) -> MemberNodeHandle {
+            MemberNodeHandle { host, inbox }
+        }
+
+        /// Runs `f` inside the node loop and returns its result, or `None` if the node is not running.
+        pub fn call<R, F>(&self, f: F) -> Option<R>
+            where R: Send + 'static,
+                  F: FnOnce(&mut DefaultMemberNode) -> R + Send + 'static {
+            let (sender, receiver) = mpsc::channel();
+            let call: NodeCall = Box::new(move |node| {
+                let _ = sender.send(f(node));
+            });
+            if self.inbox.deliver(Message::Call(call)) {
+                receiver.recv().ok()
+            } else {
+                None
+            }
+        }
+
+        /// The details of the node, or `None` if the node is not running.
+        pub fn details(&self) -> Option<MemberNodeDetails> {
+            self.call(|node| node.serialize_host_details())
+        }
+
+        pub fn shut_down(&self) {
+            self.inbox.deliver(Message::Shutdown());
         }
     }
 
+    pub struct DefaultMemberNode {
+        details: MemberNodeDetails,
+        config: NodeConfig,
+        subscribers: Vec<Sender<NodeEvent>>,
+        /// The member pinged in the current protocol period and when the ping was sent.
+        awaiting_ack: Option<(u16, Instant)>,
+        coordinate: Coordinate,
+        coordinates: HashMap<u16, Coordinate>,
+        paused: bool,
+        last_snapshot: Option<Snapshot>,
+        user_events: UserEvents,
+        query_handlers: HashMap<String, QueryHandler>,
+        /// Result streams of the queries this node sent, with the time they are closed.
+        pending_queries: HashMap<u64, (Sender<QueryEvent>, Instant)>,
+        next_query_id: u64,
+        request_handlers: HashMap<String, RequestHandler>,
+        /// Callers waiting for the responses to the requests this node sent, with the time they give up.
+        pending_requests: HashMap<u64, (Sender<Vec<u8>>, Instant)>,
+        next_request_id: u64,
+        election: Option<Election>,
+        partition: Option<PartitionDetector>,
+        health: HealthChecks,
+        last_reconnect: Instant,
+        outbox: Vec<(u16, Message)>,
+    }
+
     impl DefaultMemberNode {
-        pub fn new(host: u16, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> Arc<Mutex<DefaultMemberNode>> {
-            let (sender, receiver): (Sender<Message>, Receiver<Message>) = mpsc::channel();
-            let connection_ref = Arc::clone(&connection);
-            connection.lock().unwrap().add_connection(host, sender);
+        pub fn start(host: u16, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> MemberNodeHandle {
+            DefaultMemberNode::start_with_config(host, NodeConfig::default(), connection)
+        }
 
-            let node = DefaultMemberNode {
-                details: MemberNodeDetails::new(host)
-            };
-            let node_ref = Arc::new(Mutex::new(node));
-            let node_ref_2 = Arc::clone(&node_ref);
-            let node_ref_3 = Arc::clone(&node_ref);
+        pub fn start_with_config(host: u16, config: NodeConfig, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> MemberNodeHandle {
+            DefaultMemberNode::start_with_incarnation(host, 0, config, connection)
+        }
+
+        /// Starts a node which announces itself with the given incarnation, e.g. after a restart.
+        pub fn start_with_incarnation(host: u16, incarnation: u32, config: NodeConfig,
+                                      connection: Arc<Mutex<dyn ConnectionRegistry>>) -> MemberNodeHandle {
+            let ping_interval = config.ping_interval;
+            let (sender, receiver) = connection.lock().unwrap().inbox();
+            let ticker = sender.clone();
+            connection.lock().unwrap().add_connection(host, sender.clone());
+
+            thread::spawn(move || {
+                log!("Node {} started to listen requests", &host);
+                let mut node = DefaultMemberNode::with_incarnation(host, incarnation, config);
+                send_all(&connection, host, node.rejoin());
+                loop {
+                    node.expire_pending();
+                    let message = match receiver.recv_next(node.is_paused(), node.next_deadline()) {
+                        Ok(message) => message,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+                    if let Message::Shutdown() = message {
+                        log!("Node {} received termination message", &host);
+                        break;
+                    }
+                    send_all(&connection, host, node.handle_message(message));
+                }
+            });
 
             thread::spawn(move || {
-                println!("Node {} started to listen requests", &host);
                 loop {
-                    match receiver.recv().unwrap() {
-                        Message::Request(from, data) => {
-                            println!("Node {} received message: {}", host, data);
+                    thread::sleep(ping_interval);
+                    if !ticker.send(Message::Tick()) {
+                        break;
+                    }
+                }
+            });
+            MemberNodeHandle::new(host, Arc::new(sender))
+        }
 
-                            let mut node = node_ref.lock().unwrap();
-                            node.add_member_node(from.host);
+        /// A node announcing itself with the given incarnation, e.g. after a restart.
+        pub(crate) fn with_incarnation(host: u16, incarnation: u32, config: NodeConfig) -> DefaultMemberNode {
+            let mut node = DefaultMemberNode::new(host, config);
+            node.details.incarnation = incarnation;
+            node
+        }
 
-                            send_to(from.host, Message::Response(node.serialize_host_details(), String::from("hi")), &connection);
-                        }
-                        Message::Response(from, data) => {
-                            let mut node = node_ref.lock().unwrap();
-                            node.add_member_node(from.host);
+        pub(crate) fn new(host: u16, config: NodeConfig) -> DefaultMemberNode {
+            let mut details = MemberNodeDetails::new(host);
+            details.tags = config.tags.clone();
+            let election = config.election.clone().map(|c| Election::new(host, c, Instant::now()));
+            let partition = config.partition.clone().map(PartitionDetector::new);
+            let health = HealthChecks::new(config.health.clone());
+            DefaultMemberNode {
+                details,
+                config,
+                subscribers: Vec::new(),
+                awaiting_ack: None,
+                coordinate: Coordinate::new(),
+                coordinates: HashMap::new(),
+                paused: false,
+                last_snapshot: None,
+                user_events: UserEvents::new(),
+                query_handlers: HashMap::new(),
+                pending_queries: HashMap::new(),
+                next_query_id: 0,
+                request_handlers: HashMap::new(),
+                pending_requests: HashMap::new(),
+                next_request_id: 0,
+                election,
+                partition,
+                health,
+                last_reconnect: Instant::now(),
+                outbox: Vec::new(),
+            }
+        }
 
-                            println!("Node {} received response from Node {}: {}", host, from.host, data)
-                        }
-                        Message::Ping(from, probing_node) => {
-                            let mut node = node_ref.lock().unwrap();
-                            node.add_member_nodes(&from.members);
+        /// Applies a received message to the node state and returns the messages to be sent in reply.
+        pub fn handle_message(&mut self, message: Message) -> Vec<(u16, Message)> {
+            if self.paused && matches!(message, Message::Tick()) {
+                return Vec::new();
+            }
+            let members_before = if self.subscribers.is_empty() {
+                None
+            } else {
+                Some((self.details.members.members.clone(), self.details.members.tags.clone()))
+            };
+            let outgoing = self.apply_message(message);
+            if let Some((before, tags_before)) = members_before {
+                self.publish_member_changes(&before, &tags_before);
+            }
+            self.update_election();
+            self.update_partition();
+            outgoing
+        }
+
+        /// Adds a subscriber to the node events and sends it the current membership list.
+        pub fn subscribe(&mut self, subscriber: Sender<NodeEvent>) {
+            for host in self.details.members.hosts() {
+                let state = self.details.members.members[&host];
+                if subscriber.send(NodeEvent::MemberStateChanged(host, state)).is_err() {
+                    return;
+                }
+                if let Some(tags) = self.details.members.tags.get(&host) {
+                    if subscriber.send(NodeEvent::MemberTagsChanged(host, tags.clone())).is_err() {
+                        return;
+                    }
+                }
+            }
+            self.subscribers.push(subscriber);
+        }
+
+        /// Restores the incarnation from the snapshot of a previous run and asks the members which were alive
+        /// to take the node back, falling back to the configured seeds when there is no usable snapshot.
+        pub fn rejoin(&mut self) -> Vec<(u16, Message)> {
+            let host = self.details.host;
+            let snapshot = match self.config.snapshot_dir.as_ref().map(|dir| Snapshot::load(&Snapshot::path(dir, host))) {
+                Some(Ok(snapshot)) => Some(snapshot),
+                Some(Err(SnapshotError::Io(err))) if err.kind() == ErrorKind::NotFound => None,
+                Some(Err(err)) => {
+                    log!("Node {} ignores its snapshot - {}", host, err);
+                    None
+                }
+                None => None,
+            };
+            let targets = match snapshot {
+                Some(snapshot) => {
+                    self.details.incarnation = self.details.incarnation.max(snapshot.incarnation + 1);
+                    snapshot.alive_members()
+                }
+                None => self.config.seeds.clone(),
+            };
+            let targets: Vec<u16> = targets.into_iter().filter(|h| *h != host).collect();
+            if !targets.is_empty() {
+                log!("Node {} rejoins through {:?} with incarnation {}", host, targets, self.details.incarnation);
+            }
+            targets.into_iter()
+                .map(|to| (to, Message::Request(self.serialize_host_details(), Envelope::new("rejoin", Vec::new()))))
+                .collect()
+        }
+
+        /// Persists the membership list if it changed since the last protocol period.
+        fn save_snapshot(&mut self) {
+            let dir = match self.config.snapshot_dir.as_ref() {
+                Some(dir) => dir,
+                None => return,
+            };
+            let snapshot = Snapshot::of(&self.details);
+            if self.last_snapshot.as_ref() == Some(&snapshot) {
+                return;
+            }
+            match snapshot.save(&Snapshot::path(dir, self.details.host)) {
+                Ok(()) => self.last_snapshot = Some(snapshot),
+                Err(err) => log!("Node {} failed to save its snapshot - {}", self.details.host, err),
+            }
+        }
+
+        /// A paused node leaves the network messages in its inbox until it resumes, and skips its
+        /// protocol periods as the timers of a paused node don't fire. Calls are still answered.
+        pub fn is_paused(&self) -> bool {
+            self.paused
+        }
+
+        /// Creates a user event and delivers it to the local subscribers, the members get it with the next messages.
+        pub fn broadcast_event(&mut self, name: &str, payload: Vec<u8>) {
+            let event = self.user_events.broadcast(self.details.host, name, payload);
+            log!("Node {} broadcasts event {} at {}", self.details.host, event.name, event.ltime);
+            self.publish(NodeEvent::UserEvent(event));
+        }
+
+        /// Fans a query out to the members which aren't known to have failed and to this node itself.
+        /// The acks and responses go to `results` until the query times out.
+        pub fn query(&mut self, name: &str, payload: Vec<u8>, params: QueryParams, results: Sender<QueryEvent>) -> u64 {
+            self.next_query_id += 1;
+            let query = Query { id: self.next_query_id, name: String::from(name), payload, filter: params.filter };
+            let host = self.details.host;
+            let mut targets: Vec<u16> = self.details.members.hosts().into_iter()
+                .filter(|h| self.details.members.get_state_for(*h).is_some_and(|s| !s.is_tombstone()))
+                .collect();
+            targets.push(host);
+            log!("Node {} sends query {} to {} nodes", host, query.name, targets.len());
+            for to in targets {
+                self.outbox.push((to, Message::Query(host, query.clone())));
+            }
+            self.pending_queries.insert(query.id, (results, Instant::now() + params.timeout));
+            query.id
+        }
+
+        /// Sends a request to the node, its response body goes to `caller` unless the request times out first.
+        pub fn request(&mut self, to: u16, kind: &str, body: Vec<u8>, timeout: Duration, caller: Sender<Vec<u8>>) -> u64 {
+            self.next_request_id += 1;
+            let request = Envelope { id: self.next_request_id, kind: String::from(kind), body };
+            self.outbox.push((to, Message::Request(self.serialize_host_details(), request)));
+            self.pending_requests.insert(self.next_request_id, (caller, Instant::now() + timeout));
+            self.next_request_id
+        }
+
+        /// The earliest time a pending request or query times out, the loop of the node waits for
+        /// messages no longer than that.
+        pub(crate) fn next_deadline(&self) -> Option<Instant> {
+            let requests = self.pending_requests.values().map(|(_, deadline)| *deadline);
+            let queries = self.pending_queries.values().map(|(_, deadline)| *deadline);
+            requests.chain(queries).min()
+        }
+
+        /// Drops the requests and queries which timed out, which closes the streams of their callers.
+        pub(crate) fn expire_pending(&mut self) {
+            let now = Instant::now();
+            self.pending_requests.retain(|_, (_, deadline)| *deadline > now);
+            self.pending_queries.retain(|_, (_, deadline)| *deadline > now);
+        }
+
+        fn deliver_query_result(&mut self, id: u64, result: QueryEvent) {
+            if let Some((results, _)) = self.pending_queries.get(&id) {
+                if results.send(result).is_err() {
+                    self.pending_queries.remove(&id);
+                }
+            }
+        }
+
+        /// Re-evaluates the leader after the membership changed. A node which becomes the leader
+        /// announces its new term to the members with a user event.
+        fn update_election(&mut self) {
+            let election = match self.election.as_mut() {
+                Some(e) => e,
+                None => return,
+            };
+            if !election.update(&self.details.members, Instant::now()) {
+                return;
+            }
+            let leadership = election.leadership();
+            log!("Node {} follows leader {:?} in term {}", self.details.host, leadership.leader, leadership.term);
+            if election.is_leader() {
+                self.broadcast_event(LEADER_EVENT, leadership.term.encode());
+            }
+            self.publish(NodeEvent::LeaderChanged(leadership));
+        }
+
+        /// Publishes the partition suspected from the failures declared so far, if any.
+        fn update_partition(&mut self) {
+            let suspicion = match self.partition.as_mut() {
+                Some(p) => p.update(&self.details.members, Instant::now()),
+                None => return,
+            };
+            if let Some(suspicion) = suspicion {
+                log!("Node {} suspects a partition from {:?} in zones {:?}", self.details.host, suspicion.members, suspicion.zones);
+                self.publish(NodeEvent::PartitionSuspected(suspicion));
+            }
+        }
+
+        fn receive_user_events(&mut self, events: &[UserEvent]) {
+            for event in events {
+                if let Some(event) = self.user_events.receive(event.clone()) {
+                    if event.name == LEADER_EVENT {
+                        self.observe_term(&event);
+                    }
+                    self.publish(NodeEvent::UserEvent(event));
+                }
+            }
+        }
+
+        fn observe_term(&mut self, event: &UserEvent) {
+            let election = match self.election.as_mut() {
+                Some(e) => e,
+                None => return,
+            };
+            match u64::decode(&event.payload) {
+                Ok(term) => if election.observe_term(term) {
+                    let leadership = election.leadership();
+                    if election.is_leader() {
+                        self.broadcast_event(LEADER_EVENT, leadership.term.encode());
+                    }
+                    self.publish(NodeEvent::LeaderChanged(leadership));
+                },
+                Err(err) => log!("Node {} ignores the term announced by Node {} - {}", self.details.host, event.origin, err),
+            }
+        }
+
+        fn publish(&mut self, event: NodeEvent) {
+            self.subscribers.retain(|s| s.send(event.clone()).is_ok());
+        }
 
-                            println!("Node {} received ping request from Node {}, with members: {}", &host, from.host, node.details.members);
+        fn publish_member_changes(&mut self, before: &HashMap<u16, MemberNodeState>,
+                                  tags_before: &HashMap<u16, BTreeMap<String, String>>) {
+            let mut events = Vec::new();
+            for (host, state) in self.details.members.members.iter() {
+                if before.get(host) != Some(state) {
+                    events.push(NodeEvent::MemberStateChanged(*host, *state));
+                }
+            }
+            for (host, tags) in self.details.members.tags.iter() {
+                if tags_before.get(host) != Some(tags) {
+                    events.push(NodeEvent::MemberTagsChanged(*host, tags.clone()));
+                }
+            }
+            for host in tags_before.keys().filter(|h| !self.details.members.tags.contains_key(h)) {
+                if self.details.members.members.contains_key(host) {
+                    events.push(NodeEvent::MemberTagsChanged(*host, BTreeMap::new()));
+                }
+            }
+            for host in before.keys().filter(|h| !self.details.members.members.contains_key(h)) {
+                events.push(NodeEvent::MemberRemoved(*host));
+            }
+            for event in events {
+                self.publish(event);
+            }
+        }
+
+        fn apply_message(&mut self, message: Message) -> Vec<(u16, Message)> {
+            let host = self.details.host;
+            let mut outgoing = Vec::new();
+            let sender_tags = match &message {
+                Message::Request(from, _) | Message::Response(from, _) | Message::Ping(from, _, _) | Message::ProbeRequest(from, _) => {
+                    self.receive_user_events(from.events());
+                    outgoing.extend(self.refute(from));
+                    if !self.add_member_node(from.host, from.advertised_state(), from.incarnation) {
+                        // The sender doesn't know it's considered dead, a ping carrying the membership list tells it to refute.
+                        log!("Node {} rejects Node {} at stale incarnation {}", host, from.host, from.incarnation);
+                        outgoing.push((from.host, Message::Ping(self.serialize_host_details(), None, self.coordinate.clone())));
+                    }
+                    Some((from.host, from.tags.clone()))
+                }
+                _ => None,
+            };
+            match message {
+                Message::Request(from, request) => {
+                    log!("Node {} received {} request {} from Node {}", host, request.kind, request.id, from.host);
+                    if request.kind == PUSH_PULL {
+                        self.add_member_nodes(&from.members);
+                    }
+
+                    let body = match self.request_handlers.get(&request.kind) {
+                        Some(handler) => handler(from.host, &request.body),
+                        None => Vec::new(),
+                    };
+                    let response = Envelope { id: request.id, kind: request.kind, body };
+                    outgoing.push((from.host, Message::Response(self.serialize_host_details(), response)));
+                }
+                Message::Response(from, response) => {
+                    log!("Node {} received {} response {} from Node {}", host, response.kind, response.id, from.host);
+                    if response.kind == PUSH_PULL {
+                        self.add_member_nodes(&from.members);
+                    }
+                    if let Some((caller, _)) = self.pending_requests.remove(&response.id) {
+                        let _ = caller.send(response.body);
+                    }
+                }
+                Message::Ping(from, probing_node, coordinate) => {
+                    self.add_member_nodes(&from.members);
+                    self.remember_coordinate(from.host, coordinate);
 
-                            send_to(from.host, Message::PingResponse(host, probing_node, node.is_alive().not()), &connection);
+                    log!("Node {} received ping request from Node {}, with members: {}", &host, from.host, self.details.members);
+
+                    outgoing.push((from.host, Message::PingResponse(host, probing_node, self.is_alive().not(), self.coordinate.clone())));
+                }
+                Message::PingResponse(from, probing_node, is_timed_out, coordinate) => {
+                    match probing_node {
+                        Some(n) => {
+                            outgoing.push((n.host, Message::ProbeResponse(from, is_timed_out)));
                         }
-                        Message::PingResponse(from, probing_node, is_timed_out) => {
-                            match probing_node {
-                                Some(n) => {
-                                    send_to(n.host, Message::ProbeResponse(from, is_timed_out), &connection);
-                                }
-                                None => {
-                                    let mut node = node_ref.lock().unwrap();
-                                    if is_timed_out {
-                                        println!("Node {} didn't received ping response from Node {}. Starting to probe it...", &host, from);
-                                        node.set_member_node_state(from, MemberNodeState::Suspected);
-                                        for random_host in node.get_random_nodes(NUMBER_RANDOM_PROBE_NODES).iter() {
-                                            send_to(*random_host, Message::ProbeRequest(node.serialize_host_details(), from), &connection);
-                                        }
-                                    } else {
-                                        println!("Node {} received ping response from Node {}", &host, from);
-                                        node.set_member_node_state(from, MemberNodeState::Alive)
-                                    }
+                        None => {
+                            if let Some((_, sent_at)) = self.awaiting_ack.filter(|(member, _)| *member == from) {
+                                self.awaiting_ack = None;
+                                if !is_timed_out {
+                                    self.coordinate.update(&coordinate, sent_at.elapsed());
                                 }
                             }
-                        }
-                        Message::ProbeRequest(from, timed_out_node) => {
-                            println!("Node {} probing timed-out Node {}", &host, timed_out_node);
-
-                            let node = node_ref.lock().unwrap();
-                            send_to(timed_out_node, Message::Ping(node.serialize_host_details(), Option::Some(from)), &connection);
-                        }
-                        Message::ProbeResponse(from, is_timed_out) => {
-                            if is_timed_out.not() {
-                                println!("Node {} reported back on-line", &from);
-                                node_ref.lock().unwrap().set_member_node_state(from, MemberNodeState::Alive);
+                            if is_timed_out {
+                                log!("Node {} didn't received ping response from Node {}. Starting to probe it...", &host, from);
+                                outgoing.extend(self.suspect(from));
                             } else {
-                                println!("Node {} is still off-line", &from);
+                                log!("Node {} received ping response from Node {}", &host, from);
+                                metrics().acks_received.inc();
+                                self.set_member_node_state(from, MemberNodeState::Alive)
                             }
                         }
-                        Message::Shutdown() => {
-                            println!("Node {} received termination message", &host);
-                            break;
-                        }
                     }
+                    self.remember_coordinate(from, coordinate);
                 }
-            });
+                Message::ProbeRequest(from, timed_out_node) => {
+                    log!("Node {} probing timed-out Node {}", &host, timed_out_node);
 
-            thread::spawn(move || {
-                loop {
-                    thread::sleep(Duration::from_secs(PING_DELAY));
-                    let node = node_ref_2.lock().unwrap();
-                    if node.details.state != MemberNodeState::Failed {
-                        match node.get_random_node() {
-                            Some(m_id) => {
-                                send_to(*m_id, Message::Ping(node.serialize_host_details(), Option::None), &connection_ref);
-                            }
-                            None => {}
+                    outgoing.push((timed_out_node, Message::Ping(self.serialize_host_details(), Option::Some(from), self.coordinate.clone())));
+                }
+                Message::ProbeResponse(from, is_timed_out) => {
+                    if is_timed_out.not() {
+                        log!("Node {} reported back on-line", &from);
+                        self.set_member_node_state(from, MemberNodeState::Alive);
+                    } else {
+                        log!("Node {} is still off-line", &from);
+                    }
+                }
+                Message::Tick() => {
+                    self.user_events.expire(self.details.members.len());
+                    for member in self.details.members.reap(self.config.tombstone_timeout) {
+                        log!("Node {} removed the tombstone of Node {}", host, member);
+                    }
+                    self.save_snapshot();
+                    let members = &self.details.members;
+                    self.coordinates.retain(|h, _| members.get_state_for(*h).is_some());
+                    outgoing.extend(self.check_health());
+                    outgoing.extend(self.probe());
+                    outgoing.extend(self.reconnect());
+                }
+                Message::Call(call) => {
+                    call(self);
+                    outgoing.append(&mut self.outbox);
+                }
+                Message::Query(from, query) => {
+                    if query.matches(&self.details.tags) {
+                        outgoing.push((from, Message::QueryAck(host, query.id)));
+                        if let Some(handler) = self.query_handlers.get(&query.name) {
+                            outgoing.push((from, Message::QueryResponse(host, query.id, handler(&query.payload))));
                         }
                     }
                 }
-            });
-            node_ref_3
+                Message::QueryAck(from, id) => self.deliver_query_result(id, QueryEvent::Ack(from)),
+                Message::QueryResponse(from, id, payload) => self.deliver_query_result(id, QueryEvent::Response(from, payload)),
+                Message::Pause() => {
+                    if !self.paused {
+                        log!("Node {} paused", host);
+                        self.paused = true;
+                    }
+                }
+                Message::Unpause() => {
+                    if self.paused {
+                        log!("Node {} resumed", host);
+                        self.paused = false;
+                    }
+                }
+                Message::Shutdown() => {}
+            }
+            if let Some((from, tags)) = sender_tags {
+                self.details.members.set_tags(from, tags);
+            }
+            outgoing
+        }
+
+        /// Suspects the member pinged in the previous protocol period if it didn't ack in time,
+        /// then picks a random member to ping for the current one.
+        fn probe(&mut self) -> Vec<(u16, Message)> {
+            let mut outgoing = self.suspect_unacked();
+            if self.details.state.is_tombstone() {
+                return outgoing;
+            }
+            if let Some(member) = self.get_random_node().copied() {
+                metrics().probes_sent.inc();
+                self.awaiting_ack = Some((member, Instant::now()));
+                outgoing.push((member, Message::Ping(self.serialize_host_details(), Option::None, self.coordinate.clone())));
+            }
+            outgoing
+        }
+
+        /// Suspects the member pinged in the previous protocol period if its ack didn't arrive before the
+        /// next period started. Members which reply that they timed out are suspected as soon as they do,
+        /// this catches the ones which don't reply at all: crashed, paused or partitioned away.
+        fn suspect_unacked(&mut self) -> Vec<(u16, Message)> {
+            let member = match self.awaiting_ack.take() {
+                Some((member, _)) => member,
+                None => return Vec::new(),
+            };
+            if self.details.members.get_state_for(member).is_none_or(|s| s.is_tombstone()) {
+                return Vec::new();
+            }
+            log!("Node {} didn't receive an ack from Node {} in time. Starting to probe it...", self.details.host, member);
+            self.suspect(member)
+        }
+
+        /// Runs the health checks once per interval. The node advertises a change of its health with a new
+        /// incarnation, so the members take it over older gossip, and leaves if the checks fail for too long.
+        fn check_health(&mut self) -> Vec<(u16, Message)> {
+            let host = self.details.host;
+            if !self.is_alive() {
+                return Vec::new();
+            }
+            match self.health.run(Instant::now()) {
+                Some(HealthStatus::Healthy) if self.details.state == MemberNodeState::Degraded => {
+                    log!("Node {} passes its health checks again", host);
+                    self.advertise(MemberNodeState::Alive);
+                }
+                Some(HealthStatus::Degraded(failed)) if self.details.state == MemberNodeState::Alive => {
+                    log!("Node {} is degraded, failed health checks: {:?}", host, failed);
+                    self.advertise(MemberNodeState::Degraded);
+                }
+                Some(HealthStatus::Leave) => return self.leave(),
+                _ => {}
+            }
+            Vec::new()
+        }
+
+        fn advertise(&mut self, state: MemberNodeState) {
+            self.details.state = state;
+            self.details.incarnation += 1;
+        }
+
+        /// Leaves the cluster on purpose: the members are told right away, so they don't have to detect a failure.
+        fn leave(&mut self) -> Vec<(u16, Message)> {
+            log!("Node {} leaves the cluster after failing its health checks", self.details.host);
+            self.advertise(MemberNodeState::Left);
+            let details = self.serialize_host_details();
+            self.details.members.hosts().into_iter()
+                .filter(|h| self.details.members.get_state_for(*h).is_some_and(|s| !s.is_tombstone()))
+                .map(|h| (h, Message::Request(details.clone(), Envelope::new("leave", Vec::new()))))
+                .collect()
+        }
+
+        /// Once per reconnect interval, sends the membership list to a random failed member which isn't reaped yet.
+        /// A member which is back after a partition refutes its failure and answers with its own list.
+        fn reconnect(&mut self) -> Option<(u16, Message)> {
+            if !self.is_alive() || self.last_reconnect.elapsed() < self.config.reconnect_interval {
+                return None;
+            }
+            self.last_reconnect = Instant::now();
+            let member = self.details.members.get_random_failed_node()?;
+            log!("Node {} tries to reconnect to failed Node {}", self.details.host, member);
+            Some((member, Message::Request(self.serialize_host_details(), Envelope::new(PUSH_PULL, Vec::new()))))
+        }
+
+        /// Marks the member as suspected and asks random members to probe it.
+        fn suspect(&mut self, member: u16) -> Vec<(u16, Message)> {
+            let suspected = self.details.members.get_state_for(member) == Some(&MemberNodeState::Suspected);
+            if suspected && self.partition.as_ref().is_some_and(|p| p.throttles()) {
+                log!("Node {} keeps Node {} suspected while a partition is suspected", self.details.host, member);
+            } else {
+                self.set_member_node_state(member, MemberNodeState::Suspected);
+            }
+            self.get_random_nodes(self.config.indirect_probes)
+                .into_iter()
+                .map(|h| {
+                    metrics().indirect_probes_sent.inc();
+                    (h, Message::ProbeRequest(self.serialize_host_details(), member))
+                })
+                .collect()
+        }
+
+        pub fn host(&self) -> u16 {
+            self.details.host
+        }
+
+        pub fn add_health_check(&mut self, name: &str, check: HealthCheck) {
+            self.health.register(name, check);
+        }
+
+        pub fn coordinate(&self) -> &Coordinate {
+            &self.coordinate
+        }
+
+        /// Round-trip time between two members, either of which can be this node, estimated from the
+        /// coordinates received with the latest pings and acks.
+        pub fn estimate_rtt(&self, from: u16, to: u16) -> Option<Duration> {
+            let coordinate_of = |host: u16| if host == self.details.host {
+                Some(&self.coordinate)
+            } else {
+                self.coordinates.get(&host)
+            };
+            coordinate_of(from)?.distance_to(coordinate_of(to)?)
+        }
+
+        /// Keeps the coordinate received from a member, unless it is broken and would spoil the estimates.
+        fn remember_coordinate(&mut self, host: u16, coordinate: Coordinate) {
+            if coordinate.is_valid() {
+                self.coordinates.insert(host, coordinate);
+            } else {
+                log!("Node {} ignored the invalid coordinate of Node {}", self.details.host, host);
+            }
+        }
+
+        /// Details sent to other members, carrying the user events which are still being disseminated.
+        pub fn serialize_host_details(&self) -> MemberNodeDetails {
+            let mut details = self.details.serialize();
+            details.events = self.user_events.piggyback();
+            details
         }
 
         pub fn details(&self) -> &MemberNodeDetails {
@@ -138,16 +849,38 @@ pub mod swim_node {
 
         pub fn change_state(&mut self, state: MemberNodeState) { self.details.change_state(state); }
 
+        /// True if the node answers pings, a degraded node is alive at the protocol level.
         fn is_alive(&self) -> bool {
-            *self.details.state() == MemberNodeState::Alive
+            matches!(self.details.state(), MemberNodeState::Alive | MemberNodeState::Degraded)
         }
 
         fn get_random_nodes(&self, number: usize) -> Vec<u16> {
             self.details.members.get_random_nodes(number)
         }
 
-        fn add_member_node(&mut self, host: u16) {
-            self.details.members.add(host);
+        /// Adds a member which contacted this node in the state it advertises, returns `false` if it's
+        /// a tombstone and the member didn't move to a higher incarnation since.
+        fn add_member_node(&mut self, host: u16, state: MemberNodeState, incarnation: u32) -> bool {
+            let previous = self.details.members.get_state_for(host).copied();
+            let added = self.details.members.merge(host, state, incarnation);
+            if added {
+                record_transition(previous, state);
+            }
+            added
+        }
+
+        /// Answers a member which declared this node failed or left at its current incarnation: the node moves
+        /// to the next incarnation and asks the member to take it back, so a false positive doesn't stick.
+        fn refute(&mut self, from: &MemberNodeDetails) -> Option<(u16, Message)> {
+            let host = self.details.host;
+            from.members.get_state_for(host).filter(|s| s.is_tombstone())?;
+            let declared = from.members.incarnation_of(host).unwrap_or(0);
+            if declared < self.details.incarnation || !self.is_alive() {
+                return None;
+            }
+            self.details.incarnation = declared + 1;
+            log!("Node {} refutes its failure reported by Node {} with incarnation {}", host, from.host, self.details.incarnation);
+            Some((from.host, Message::Request(self.serialize_host_details(), Envelope::new("rejoin", Vec::new()))))
         }
 
         fn add_member_nodes(&mut self, members: &MemberNodesRegistry) {
@@ -159,12 +892,23 @@ pub mod swim_node {
         }
 
         fn set_member_node_state(&mut self, member_node_id: u16, state: MemberNodeState) {
+            let previous = self.details.members.get_state_for(member_node_id).copied();
             self.details.members.set_node_state(member_node_id, state);
+            if let Some(current) = self.details.members.get_state_for(member_node_id) {
+                record_transition(previous, *current);
+            }
         }
     }
 
-    fn send_to(host: u16, message: Message, connection_factory: &Arc<Mutex<dyn ConnectionRegistry>>) {
-        connection_factory.lock().unwrap().send_to(host, message);
+    fn record_transition(previous: Option<MemberNodeState>, current: MemberNodeState) {
+        let metrics = metrics();
+        match (previous, current) {
+            (Some(MemberNodeState::Suspected), MemberNodeState::Alive) => metrics.suspicions_refuted.inc(),
+            (Some(MemberNodeState::Failed), MemberNodeState::Alive) => metrics.false_positives.inc(),
+            (Some(MemberNodeState::Suspected), MemberNodeState::Failed) => metrics.failures_declared.inc(),
+            (Some(MemberNodeState::Alive), MemberNodeState::Suspected) => metrics.suspicions_raised.inc(),
+            _ => {}
+        }
     }
 
     #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -172,6 +916,17 @@ pub mod swim_node {
         Alive,
         Suspected,
         Failed,
+        /// The member left the cluster on purpose.
+        Left,
+        /// The member answers pings but its health checks fail.
+        Degraded,
+    }
+
+    impl MemberNodeState {
+        /// Failed and left members stay in the membership list as tombstones until they are reaped.
+        pub fn is_tombstone(&self) -> bool {
+            matches!(self, MemberNodeState::Failed | MemberNodeState::Left)
+        }
     }
 
     impl Display for MemberNodeState {
@@ -183,29 +938,62 @@ pub mod swim_node {
     #[derive(Clone)]
     pub struct MemberNodeDetails {
         host: u16,
+        incarnation: u32,
         state: MemberNodeState,
         members: MemberNodesRegistry,
+        events: Vec<UserEvent>,
+        tags: BTreeMap<String, String>,
     }
 
     impl MemberNodeDetails {
         pub fn new(host: u16) -> Self {
             MemberNodeDetails {
                 host,
+                incarnation: 0,
                 state: MemberNodeState::Alive,
                 members: MemberNodesRegistry::new(),
+                events: Vec::new(),
+                tags: BTreeMap::new(),
             }
         }
 
+        /// Details of a node with the given membership list, e.g. to measure the size of a full membership dump.
+        pub fn with_members(host: u16, members: MemberNodesRegistry) -> Self {
+            MemberNodeDetails { members, ..MemberNodeDetails::new(host) }
+        }
+
+        /// Details read back from their encoding.
+        pub fn from_parts(host: u16, incarnation: u32, state: MemberNodeState, members: MemberNodesRegistry,
+                          events: Vec<UserEvent>, tags: BTreeMap<String, String>) -> Self {
+            MemberNodeDetails { host, incarnation, state, members, events, tags }
+        }
+
         pub fn host(&self) -> u16 { self.host }
 
+        /// Number of times the node has been restarted or refuted a report of its failure.
+        pub fn incarnation(&self) -> u32 { self.incarnation }
+
         pub fn name(&self) -> String { format!("node-{}", self.host) }
 
         pub fn state(&self) -> &MemberNodeState { &self.state }
 
         pub fn members(&self) -> &MemberNodesRegistry { &self.members }
 
+        pub fn tags(&self) -> &BTreeMap<String, String> { &self.tags }
+
+        /// User events piggybacked by the sender.
+        pub fn events(&self) -> &[UserEvent] { &self.events }
+
         pub fn change_state(&mut self, state: MemberNodeState) { self.state = state }
 
+        /// The state the members record for the node when it contacts them.
+        pub fn advertised_state(&self) -> MemberNodeState {
+            match self.state {
+                MemberNodeState::Degraded | MemberNodeState::Left => self.state,
+                _ => MemberNodeState::Alive,
+            }
+        }
+
         pub fn serialize(&self) -> MemberNodeDetails {
             let mut new_members = HashMap::new();
             let members = &self.members.members;
@@ -214,10 +1002,17 @@ pub mod swim_node {
             }
             MemberNodeDetails {
                 host: self.host,
-                state: self.state.clone(),
+                incarnation: self.incarnation,
+                state: self.state,
                 members: MemberNodesRegistry {
-                    members: new_members
+                    members: new_members,
+                    tags: self.members.tags.clone(),
+                    records: self.members.records.iter()
+                        .map(|(host, record)| (*host, MemberRecord { tombstoned_at: None, ..*record }))
+                        .collect(),
                 },
+                events: self.events.clone(),
+                tags: self.tags.clone(),
             }
         }
     }
@@ -225,45 +1020,159 @@ pub mod swim_node {
     #[derive(Clone, Debug)]
     pub struct MemberNodesRegistry {
         members: HashMap<u16, MemberNodeState>,
+        tags: HashMap<u16, BTreeMap<String, String>>,
+        records: HashMap<u16, MemberRecord>,
+    }
+
+    #[derive(Clone, Copy, Default, Debug)]
+    struct MemberRecord {
+        incarnation: u32,
+        /// When the member became a tombstone, in local time, so it isn't gossiped.
+        tombstoned_at: Option<Instant>,
+        /// The member advertised itself as degraded, an ack doesn't make it alive.
+        degraded: bool,
+    }
+
+    impl Default for MemberNodesRegistry {
+        fn default() -> Self {
+            MemberNodesRegistry::new()
+        }
     }
 
     impl MemberNodesRegistry {
         pub fn new() -> Self {
             MemberNodesRegistry {
-                members: HashMap::new()
+                members: HashMap::new(),
+                tags: HashMap::new(),
+                records: HashMap::new(),
             }
         }
 
-        pub fn add(&mut self, host: u16) {
-            self.members.insert(host, MemberNodeState::Alive);
+        /// Adds an alive member at the given incarnation, returns `false` if the member is a tombstone
+        /// of the same or a higher incarnation, or already known at a higher incarnation.
+        pub fn add(&mut self, host: u16, incarnation: u32) -> bool {
+            self.merge(host, MemberNodeState::Alive, incarnation)
         }
 
+        /// Merges the membership list gossiped by another member. Tombstones are kept rather than removed,
+        /// so stale gossip can't bring a dead member back unless it carries a higher incarnation. The tombstone
+        /// of an unknown member is ignored, otherwise members which reap it at different times would keep
+        /// handing it back to each other.
         pub fn add_all(&mut self, self_id: u16, members: &MemberNodesRegistry) {
-            for host in members.members.keys().filter(|i| **i != self_id) {
-                match members.members.get(host) {
-                    Some(state) => {
-                        if *state == MemberNodeState::Failed {
-                            self.members.remove(host);
-                        } else {
-                            self.members.insert(*host, *state);
-                        }
+            for (host, state) in members.members.iter().filter(|(host, _)| **host != self_id) {
+                if state.is_tombstone() && !self.members.contains_key(host) {
+                    continue;
+                }
+                let incarnation = members.incarnation_of(*host).unwrap_or(0);
+                if self.merge(*host, *state, incarnation) && !state.is_tombstone() {
+                    if let Some(tags) = members.tags.get(host) {
+                        self.tags.insert(*host, tags.clone());
                     }
-                    None => {}
                 }
             }
         }
 
+        /// Applies the state of a member at an incarnation. A tombstone only gives way to a higher incarnation,
+        /// and a live entry to the same or a higher one. Returns `false` if the state is stale.
+        pub fn merge(&mut self, host: u16, state: MemberNodeState, incarnation: u32) -> bool {
+            let known = self.incarnation_of(host).unwrap_or(0);
+            let accepted = match self.members.get(&host) {
+                None => true,
+                Some(MemberNodeState::Left) if state == MemberNodeState::Failed => incarnation > known,
+                Some(current) if current.is_tombstone() && !state.is_tombstone() => incarnation > known,
+                Some(_) => incarnation >= known,
+            };
+            if !accepted {
+                return false;
+            }
+            self.members.insert(host, state);
+            let record = self.records.entry(host).or_default();
+            record.incarnation = incarnation;
+            if matches!(state, MemberNodeState::Alive | MemberNodeState::Degraded) {
+                record.degraded = state == MemberNodeState::Degraded;
+            }
+            if state.is_tombstone() {
+                record.tombstoned_at.get_or_insert_with(Instant::now);
+            } else {
+                record.tombstoned_at = None;
+            }
+            true
+        }
+
+        /// Changes the state of a known member as seen by this node. Suspecting a suspected member declares
+        /// it failed, a degraded member which acks stays degraded, and a tombstone stays one, only a higher
+        /// incarnation brings the member back.
         pub fn set_node_state(&mut self, host: u16, state: MemberNodeState) {
-            match self.members.get(&host) {
-                Some(m) => {
-                    if state == MemberNodeState::Suspected && *m == MemberNodeState::Suspected {
-                        self.members.insert(host, MemberNodeState::Failed);
-                    } else {
-                        self.members.insert(host, state);
-                    }
-                }
-                None => {}
+            let current = match self.members.get(&host) {
+                Some(current) => *current,
+                None => return,
             };
+            let degraded = self.records.get(&host).is_some_and(|r| r.degraded);
+            let state = match state {
+                MemberNodeState::Suspected if current == MemberNodeState::Suspected => MemberNodeState::Failed,
+                MemberNodeState::Alive if degraded => MemberNodeState::Degraded,
+                _ => state,
+            };
+            if current.is_tombstone() && !state.is_tombstone() {
+                return;
+            }
+            self.members.insert(host, state);
+            if state.is_tombstone() {
+                let record = self.records.entry(host).or_default();
+                record.tombstoned_at.get_or_insert_with(Instant::now);
+            }
+        }
+
+        /// Removes the tombstones older than the timeout and returns their hosts.
+        pub fn reap(&mut self, timeout: Duration) -> Vec<u16> {
+            let expired: Vec<u16> = self.records.iter()
+                .filter(|(_, record)| record.tombstoned_at.is_some_and(|at| at.elapsed() >= timeout))
+                .map(|(host, _)| *host)
+                .collect();
+            for host in expired.iter() {
+                self.members.remove(host);
+                self.tags.remove(host);
+                self.records.remove(host);
+            }
+            expired
+        }
+
+        /// The highest incarnation of the member this node heard of.
+        pub fn incarnation_of(&self, host: u16) -> Option<u32> {
+            self.members.get(&host).map(|_| self.records.get(&host).map_or(0, |r| r.incarnation))
+        }
+
+        /// When the member became a tombstone, `None` if it's not one.
+        pub fn tombstoned_since(&self, host: u16) -> Option<Instant> {
+            self.records.get(&host).and_then(|r| r.tombstoned_at)
+        }
+
+        /// Records the tags announced by a known member.
+        pub fn set_tags(&mut self, host: u16, tags: BTreeMap<String, String>) {
+            if tags.is_empty() {
+                self.tags.remove(&host);
+            } else if self.members.contains_key(&host) {
+                self.tags.insert(host, tags);
+            }
+        }
+
+        pub fn get_tags_for(&self, host: u16) -> Option<&BTreeMap<String, String>> {
+            self.tags.get(&host)
+        }
+
+        pub fn len(&self) -> usize {
+            self.members.len()
+        }
+
+        /// Known members in ascending order.
+        pub fn hosts(&self) -> Vec<u16> {
+            let mut hosts: Vec<u16> = self.members.keys().cloned().collect();
+            hosts.sort_unstable();
+            hosts
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.members.is_empty()
         }
 
         pub fn get_state_for(&self, host: u16) -> Option<&MemberNodeState> {
@@ -272,7 +1181,7 @@ pub mod swim_node {
 
         pub fn get_random_node(&self) -> Option<&u16> {
             let members: Vec<&u16> = self.members.keys()
-                .filter(|host| self.is_host_not_failed(host))
+                .filter(|host| !self.members[*host].is_tombstone())
                 .collect();
             if members.is_empty() {
                 None
@@ -283,19 +1192,30 @@ pub mod swim_node {
             }
         }
 
+        /// A random failed member, members which left on purpose aren't contacted again.
+        pub fn get_random_failed_node(&self) -> Option<u16> {
+            let failed: Vec<u16> = self.members.iter()
+                .filter(|(_, state)| **state == MemberNodeState::Failed)
+                .map(|(host, _)| *host)
+                .collect();
+            if failed.is_empty() {
+                None
+            } else {
+                Some(failed[thread_rng().gen_range(0..failed.len())])
+            }
+        }
+
+        /// Random members which answer pings, to probe a suspected member through. Degraded members still
+        /// answer, so they are picked as well.
         pub fn get_random_nodes(&self, number: usize) -> Vec<u16> {
             use rand::prelude::*;
             let mut members: Vec<&u16> = self.members.keys()
-                .filter(|host| *self.members.get(host).unwrap() == MemberNodeState::Alive)
+                .filter(|host| matches!(self.members[*host], MemberNodeState::Alive | MemberNodeState::Degraded))
                 .collect();
 
             members.shuffle(&mut rand::thread_rng());
             members.iter().take(number).cloned().cloned().collect()
         }
-
-        fn is_host_not_failed(&self, host: &&u16) -> bool {
-            *self.members.get(host).unwrap() != MemberNodeState::Failed
-        }
     }
 
     impl Display for MemberNodesRegistry {
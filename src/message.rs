@@ -1,13 +1,369 @@
 pub mod swim_node {
-    use crate::member_node::swim_node::MemberNodeDetails;
+    use crate::coordinate::swim_node::{Coordinate, DIMENSIONS};
+    use std::collections::BTreeMap;
+    use std::convert::TryInto;
+    use crate::member_node::swim_node::{MemberNodeDetails, MemberNodeState, MemberNodesRegistry, NodeCall};
+    use crate::query::swim_node::Query;
+    use crate::rpc::swim_node::Envelope;
+    use crate::user_event::swim_node::UserEvent;
 
     pub enum Message {
-        Request(MemberNodeDetails, String),
-        Response(MemberNodeDetails, String),
-        Ping(MemberNodeDetails, Option<MemberNodeDetails>),
-        PingResponse(u16, Option<MemberNodeDetails>, bool),
+        /// A direct request which also makes the receiver add the sender to its members.
+        Request(MemberNodeDetails, Envelope),
+        Response(MemberNodeDetails, Envelope),
+        /// Pings a member, directly or on behalf of the probing node, with the coordinate of the sender.
+        Ping(MemberNodeDetails, Option<MemberNodeDetails>, Coordinate),
+        /// Acks a ping with the coordinate of the pinged member, or reports that it's down.
+        PingResponse(u16, Option<MemberNodeDetails>, bool, Coordinate),
         ProbeRequest(MemberNodeDetails, u16),
         ProbeResponse(u16, bool),
+        /// A cluster query sent by the originator host.
+        Query(u16, Query),
+        /// Confirms that the host received the query with the given id and matches its filter.
+        QueryAck(u16, u64),
+        QueryResponse(u16, u64, Vec<u8>),
+        /// Starts a new protocol period of the receiving node.
+        Tick(),
+        /// Runs a request against the state of the receiving node.
+        Call(NodeCall),
+        /// Makes the receiving node leave network messages in its inbox and skip its protocol periods.
+        Pause(),
+        /// Resumes a paused node, which then handles the messages received in the meantime.
+        Unpause(),
         Shutdown(),
     }
-}
\ No newline at end of file
+
+    /// Importance of a message when it competes for limited capacity, from the least to the most important.
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    pub enum MessagePriority {
+        Gossip,
+        Probe,
+        Ack,
+        Control,
+    }
+
+    const DETAILS_HEADER_LEN: usize = 2 + 4 + 1 + 4;
+    const MEMBER_LEN: usize = 2 + 1 + 4 + 1;
+    const USER_EVENT_HEADER_LEN: usize = 2 + 8 + 4;
+    const COORDINATE_LEN: usize = (DIMENSIONS + 2) * 8;
+
+    fn short_str_len(s: &str) -> usize {
+        1 + s.len().min(u8::MAX as usize)
+    }
+
+    fn tags_len(tags: &BTreeMap<String, String>) -> usize {
+        1 + tags.iter().take(u8::MAX as usize).map(|(k, v)| short_str_len(k) + short_str_len(v)).sum::<usize>()
+    }
+
+    fn registry_len(members: &MemberNodesRegistry) -> usize {
+        members.hosts().into_iter()
+            .map(|host| MEMBER_LEN - 1 + members.get_tags_for(host).map_or(1, tags_len))
+            .sum()
+    }
+
+    fn details_len(details: &MemberNodeDetails) -> usize {
+        let events: usize = details.events().iter()
+            .map(|e| USER_EVENT_HEADER_LEN + short_str_len(&e.name) + e.payload.len())
+            .sum();
+        DETAILS_HEADER_LEN + registry_len(details.members()) + 2 + events + tags_len(details.tags())
+    }
+
+    fn optional_details_len(details: &Option<MemberNodeDetails>) -> usize {
+        1 + details.as_ref().map_or(0, details_len)
+    }
+
+    impl Message {
+        /// Estimated number of bytes the message would take on the wire. Local control messages take none.
+        pub fn encoded_len(&self) -> usize {
+            let payload = match self {
+                Message::Request(from, envelope) | Message::Response(from, envelope) =>
+                    details_len(from) + 8 + short_str_len(&envelope.kind) + 4 + envelope.body.len(),
+                Message::Ping(from, probing_node, _) => details_len(from) + optional_details_len(probing_node) + COORDINATE_LEN,
+                Message::PingResponse(_, probing_node, _, _) => 2 + optional_details_len(probing_node) + 1 + COORDINATE_LEN,
+                Message::ProbeRequest(from, _) => details_len(from) + 2,
+                Message::ProbeResponse(..) => 2 + 1,
+                Message::Query(_, query) => 2 + 8 + short_str_len(&query.name) + 4 + query.payload.len() + tags_len(&query.filter),
+                Message::QueryAck(..) => 2 + 8,
+                Message::QueryResponse(_, _, payload) => 2 + 8 + 4 + payload.len(),
+                Message::Tick() | Message::Call(..) | Message::Pause() | Message::Unpause() | Message::Shutdown() => return 0,
+            };
+            1 + payload
+        }
+
+        pub fn priority(&self) -> MessagePriority {
+            match self {
+                Message::Request(..) | Message::Response(..) | Message::Query(..) | Message::QueryResponse(..) => MessagePriority::Gossip,
+                Message::Ping(..) | Message::ProbeRequest(..) => MessagePriority::Probe,
+                Message::PingResponse(..) | Message::ProbeResponse(..) | Message::QueryAck(..) => MessagePriority::Ack,
+                Message::Tick() | Message::Call(..) | Message::Pause() | Message::Unpause() | Message::Shutdown() => MessagePriority::Control,
+            }
+        }
+
+        /// Encodes the message in the wire format: a type byte followed by the fields in big-endian order,
+        /// strings with a one-byte and payloads with a four-byte length. Local control messages encode to nothing.
+        pub fn encode(&self) -> Vec<u8> {
+            let mut out = Vec::with_capacity(self.encoded_len());
+            match self {
+                Message::Request(from, envelope) | Message::Response(from, envelope) => {
+                    out.push(if let Message::Request(..) = self { 1 } else { 2 });
+                    encode_details(&mut out, from);
+                    out.extend_from_slice(&envelope.id.to_be_bytes());
+                    put_short_str(&mut out, &envelope.kind);
+                    put_bytes(&mut out, &envelope.body);
+                }
+                Message::Ping(from, probing_node, coordinate) => {
+                    out.push(3);
+                    encode_details(&mut out, from);
+                    encode_optional_details(&mut out, probing_node);
+                    encode_coordinate(&mut out, coordinate);
+                }
+                Message::PingResponse(from, probing_node, is_timed_out, coordinate) => {
+                    out.push(4);
+                    out.extend_from_slice(&from.to_be_bytes());
+                    encode_optional_details(&mut out, probing_node);
+                    out.push(*is_timed_out as u8);
+                    encode_coordinate(&mut out, coordinate);
+                }
+                Message::ProbeRequest(from, timed_out_node) => {
+                    out.push(5);
+                    encode_details(&mut out, from);
+                    out.extend_from_slice(&timed_out_node.to_be_bytes());
+                }
+                Message::ProbeResponse(from, is_timed_out) => {
+                    out.push(6);
+                    out.extend_from_slice(&from.to_be_bytes());
+                    out.push(*is_timed_out as u8);
+                }
+                Message::Query(from, query) => {
+                    out.push(7);
+                    out.extend_from_slice(&from.to_be_bytes());
+                    out.extend_from_slice(&query.id.to_be_bytes());
+                    put_short_str(&mut out, &query.name);
+                    put_bytes(&mut out, &query.payload);
+                    put_tags(&mut out, &query.filter);
+                }
+                Message::QueryAck(from, id) => {
+                    out.push(8);
+                    out.extend_from_slice(&from.to_be_bytes());
+                    out.extend_from_slice(&id.to_be_bytes());
+                }
+                Message::QueryResponse(from, id, payload) => {
+                    out.push(9);
+                    out.extend_from_slice(&from.to_be_bytes());
+                    out.extend_from_slice(&id.to_be_bytes());
+                    put_bytes(&mut out, payload);
+                }
+                Message::Tick() | Message::Call(..) | Message::Pause() | Message::Unpause() | Message::Shutdown() => {}
+            }
+            out
+        }
+
+        /// Decodes a message encoded by `encode`.
+        pub fn decode(bytes: &[u8]) -> Result<Message, DecodeError> {
+            let mut reader = Reader { bytes };
+            let message = match reader.u8()? {
+                kind @ 1 | kind @ 2 => {
+                    let from = reader.details()?;
+                    let envelope = Envelope { id: reader.u64()?, kind: reader.short_str()?, body: reader.bytes()? };
+                    if kind == 1 { Message::Request(from, envelope) } else { Message::Response(from, envelope) }
+                }
+                3 => Message::Ping(reader.details()?, reader.optional_details()?, reader.coordinate()?),
+                4 => Message::PingResponse(reader.u16()?, reader.optional_details()?, reader.bool()?, reader.coordinate()?),
+                5 => Message::ProbeRequest(reader.details()?, reader.u16()?),
+                6 => Message::ProbeResponse(reader.u16()?, reader.bool()?),
+                7 => {
+                    let from = reader.u16()?;
+                    let query = Query { id: reader.u64()?, name: reader.short_str()?, payload: reader.bytes()?, filter: reader.tags()? };
+                    Message::Query(from, query)
+                }
+                8 => Message::QueryAck(reader.u16()?, reader.u64()?),
+                9 => Message::QueryResponse(reader.u16()?, reader.u64()?, reader.bytes()?),
+                kind => return Err(DecodeError(format!("unknown message type {}", kind))),
+            };
+            if !reader.bytes.is_empty() {
+                return Err(DecodeError(format!("{} bytes after the message", reader.bytes.len())));
+            }
+            Ok(message)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub struct DecodeError(pub String);
+
+    /// Reads the fields of an encoded message in order.
+    struct Reader<'a> {
+        bytes: &'a [u8],
+    }
+
+    impl<'a> Reader<'a> {
+        fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+            if self.bytes.len() < len {
+                return Err(DecodeError(String::from("truncated message")));
+            }
+            let (field, rest) = self.bytes.split_at(len);
+            self.bytes = rest;
+            Ok(field)
+        }
+
+        fn u8(&mut self) -> Result<u8, DecodeError> {
+            Ok(self.take(1)?[0])
+        }
+
+        fn bool(&mut self) -> Result<bool, DecodeError> {
+            Ok(self.u8()? != 0)
+        }
+
+        fn u16(&mut self) -> Result<u16, DecodeError> {
+            Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+        }
+
+        fn u32(&mut self) -> Result<u32, DecodeError> {
+            Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+        }
+
+        fn u64(&mut self) -> Result<u64, DecodeError> {
+            Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+        }
+
+        fn f64(&mut self) -> Result<f64, DecodeError> {
+            Ok(f64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+        }
+
+        /// A string cut at 255 bytes may end in a partial character, which is replaced.
+        fn short_str(&mut self) -> Result<String, DecodeError> {
+            let len = self.u8()? as usize;
+            Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+        }
+
+        fn bytes(&mut self) -> Result<Vec<u8>, DecodeError> {
+            let len = self.u32()? as usize;
+            Ok(self.take(len)?.to_vec())
+        }
+
+        fn tags(&mut self) -> Result<BTreeMap<String, String>, DecodeError> {
+            let mut tags = BTreeMap::new();
+            for _ in 0..self.u8()? {
+                tags.insert(self.short_str()?, self.short_str()?);
+            }
+            Ok(tags)
+        }
+
+        fn state(&mut self) -> Result<MemberNodeState, DecodeError> {
+            match self.u8()? {
+                0 => Ok(MemberNodeState::Alive),
+                1 => Ok(MemberNodeState::Suspected),
+                2 => Ok(MemberNodeState::Failed),
+                3 => Ok(MemberNodeState::Left),
+                4 => Ok(MemberNodeState::Degraded),
+                code => Err(DecodeError(format!("unknown member state {}", code))),
+            }
+        }
+
+        fn registry(&mut self) -> Result<MemberNodesRegistry, DecodeError> {
+            let mut members = MemberNodesRegistry::new();
+            for _ in 0..self.u32()? {
+                let (host, state, incarnation) = (self.u16()?, self.state()?, self.u32()?);
+                members.merge(host, state, incarnation);
+                members.set_tags(host, self.tags()?);
+            }
+            Ok(members)
+        }
+
+        fn details(&mut self) -> Result<MemberNodeDetails, DecodeError> {
+            let (host, incarnation, state) = (self.u16()?, self.u32()?, self.state()?);
+            let members = self.registry()?;
+            let mut events = Vec::new();
+            for _ in 0..self.u16()? {
+                let (origin, ltime) = (self.u16()?, self.u64()?);
+                events.push(UserEvent { name: self.short_str()?, payload: self.bytes()?, ltime, origin });
+            }
+            Ok(MemberNodeDetails::from_parts(host, incarnation, state, members, events, self.tags()?))
+        }
+
+        fn optional_details(&mut self) -> Result<Option<MemberNodeDetails>, DecodeError> {
+            match self.u8()? {
+                0 => Ok(None),
+                _ => Ok(Some(self.details()?)),
+            }
+        }
+
+        fn coordinate(&mut self) -> Result<Coordinate, DecodeError> {
+            let mut vec = [0.0; DIMENSIONS];
+            for v in vec.iter_mut() {
+                *v = self.f64()?;
+            }
+            Ok(Coordinate { vec, height: self.f64()?, error: self.f64()? })
+        }
+    }
+
+    fn put_short_str(out: &mut Vec<u8>, s: &str) {
+        let bytes = &s.as_bytes()[..s.len().min(u8::MAX as usize)];
+        out.push(bytes.len() as u8);
+        out.extend_from_slice(bytes);
+    }
+
+    fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+        out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        out.extend_from_slice(bytes);
+    }
+
+    fn put_tags(out: &mut Vec<u8>, tags: &BTreeMap<String, String>) {
+        out.push(tags.len().min(u8::MAX as usize) as u8);
+        for (key, value) in tags.iter().take(u8::MAX as usize) {
+            put_short_str(out, key);
+            put_short_str(out, value);
+        }
+    }
+
+    fn state_code(state: MemberNodeState) -> u8 {
+        match state {
+            MemberNodeState::Alive => 0,
+            MemberNodeState::Suspected => 1,
+            MemberNodeState::Failed => 2,
+            MemberNodeState::Left => 3,
+            MemberNodeState::Degraded => 4,
+        }
+    }
+
+    /// Appends the membership list: the host, state, incarnation and tags of every member.
+    pub fn encode_registry(out: &mut Vec<u8>, members: &MemberNodesRegistry) {
+        out.extend_from_slice(&(members.len() as u32).to_be_bytes());
+        for host in members.hosts() {
+            out.extend_from_slice(&host.to_be_bytes());
+            out.push(members.get_state_for(host).map_or(0, |s| state_code(*s)));
+            out.extend_from_slice(&members.incarnation_of(host).unwrap_or(0).to_be_bytes());
+            put_tags(out, members.get_tags_for(host).unwrap_or(&BTreeMap::new()));
+        }
+    }
+
+    fn encode_details(out: &mut Vec<u8>, details: &MemberNodeDetails) {
+        out.extend_from_slice(&details.host().to_be_bytes());
+        out.extend_from_slice(&details.incarnation().to_be_bytes());
+        out.push(state_code(*details.state()));
+        encode_registry(out, details.members());
+        out.extend_from_slice(&(details.events().len() as u16).to_be_bytes());
+        for event in details.events() {
+            out.extend_from_slice(&event.origin.to_be_bytes());
+            out.extend_from_slice(&event.ltime.to_be_bytes());
+            put_short_str(out, &event.name);
+            put_bytes(out, &event.payload);
+        }
+        put_tags(out, details.tags());
+    }
+
+    fn encode_optional_details(out: &mut Vec<u8>, details: &Option<MemberNodeDetails>) {
+        match details {
+            Some(details) => {
+                out.push(1);
+                encode_details(out, details);
+            }
+            None => out.push(0),
+        }
+    }
+
+    fn encode_coordinate(out: &mut Vec<u8>, coordinate: &Coordinate) {
+        for v in coordinate.vec.iter().chain([coordinate.height, coordinate.error].iter()) {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+    }
+}
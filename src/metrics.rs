@@ -0,0 +1,209 @@
+pub mod swim_node {
+    use std::fmt::Write as FmtWrite;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+    use std::sync::OnceLock;
+    use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+    use std::thread;
+    use crate::log;
+
+    const MESSAGE_SIZE_BUCKETS: [u64; 8] = [64, 128, 256, 512, 1024, 4096, 16384, 65536];
+
+    #[derive(Default)]
+    pub struct Counter {
+        value: AtomicU64,
+    }
+
+    impl Counter {
+        pub fn inc(&self) {
+            self.add(1);
+        }
+
+        pub fn add(&self, value: u64) {
+            self.value.fetch_add(value, Ordering::Relaxed);
+        }
+
+        pub fn get(&self) -> u64 {
+            self.value.load(Ordering::Relaxed)
+        }
+    }
+
+    #[derive(Default)]
+    pub struct Gauge {
+        value: AtomicI64,
+    }
+
+    impl Gauge {
+        pub fn inc(&self) {
+            self.value.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub fn dec(&self) {
+            self.sub(1);
+        }
+
+        pub fn sub(&self, value: i64) {
+            self.value.fetch_sub(value, Ordering::Relaxed);
+        }
+
+        pub fn get(&self) -> i64 {
+            self.value.load(Ordering::Relaxed)
+        }
+    }
+
+    pub struct Histogram {
+        buckets: &'static [u64],
+        counts: Vec<AtomicU64>,
+        sum: AtomicU64,
+        count: AtomicU64,
+    }
+
+    impl Histogram {
+        pub fn new(buckets: &'static [u64]) -> Histogram {
+            Histogram {
+                buckets,
+                counts: buckets.iter().map(|_| AtomicU64::new(0)).collect(),
+                sum: AtomicU64::new(0),
+                count: AtomicU64::new(0),
+            }
+        }
+
+        pub fn observe(&self, value: u64) {
+            if let Some(i) = self.buckets.iter().position(|b| value <= *b) {
+                self.counts[i].fetch_add(1, Ordering::Relaxed);
+            }
+            self.sum.fetch_add(value, Ordering::Relaxed);
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub fn count(&self) -> u64 {
+            self.count.load(Ordering::Relaxed)
+        }
+
+        pub fn sum(&self) -> u64 {
+            self.sum.load(Ordering::Relaxed)
+        }
+    }
+
+    /// Protocol counters shared by all nodes of the process.
+    pub struct Metrics {
+        pub probes_sent: Counter,
+        pub acks_received: Counter,
+        pub indirect_probes_sent: Counter,
+        pub suspicions_raised: Counter,
+        pub suspicions_refuted: Counter,
+        pub failures_declared: Counter,
+        pub false_positives: Counter,
+        pub messages_sent: Counter,
+        pub messages_dropped: Counter,
+        pub messages_throttled: Counter,
+        pub compression_saved_bytes: Counter,
+        pub message_size_bytes: Histogram,
+        pub inbox_depth: Gauge,
+        pub nodes_added: Counter,
+        pub requests_routed: Counter,
+    }
+
+    impl Metrics {
+        fn new() -> Metrics {
+            Metrics {
+                probes_sent: Counter::default(),
+                acks_received: Counter::default(),
+                indirect_probes_sent: Counter::default(),
+                suspicions_raised: Counter::default(),
+                suspicions_refuted: Counter::default(),
+                failures_declared: Counter::default(),
+                false_positives: Counter::default(),
+                messages_sent: Counter::default(),
+                messages_dropped: Counter::default(),
+                messages_throttled: Counter::default(),
+                compression_saved_bytes: Counter::default(),
+                message_size_bytes: Histogram::new(&MESSAGE_SIZE_BUCKETS),
+                inbox_depth: Gauge::default(),
+                nodes_added: Counter::default(),
+                requests_routed: Counter::default(),
+            }
+        }
+
+        /// Renders all metrics in the Prometheus text exposition format.
+        pub fn render(&self) -> String {
+            let mut out = String::new();
+            let counters = [
+                ("swim_probes_sent_total", "Direct pings sent by nodes.", &self.probes_sent),
+                ("swim_acks_received_total", "Ping acks received by nodes.", &self.acks_received),
+                ("swim_indirect_probes_sent_total", "Indirect probe requests sent for timed-out members.", &self.indirect_probes_sent),
+                ("swim_suspicions_raised_total", "Members marked as suspected.", &self.suspicions_raised),
+                ("swim_suspicions_refuted_total", "Suspected members which turned out to be alive.", &self.suspicions_refuted),
+                ("swim_failures_declared_total", "Members marked as failed.", &self.failures_declared),
+                ("swim_false_positives_total", "Failed members which turned out to be alive.", &self.false_positives),
+                ("swim_messages_sent_total", "Messages sent through the connection registry.", &self.messages_sent),
+                ("swim_messages_dropped_total", "Messages dropped by full node inboxes.", &self.messages_dropped),
+                ("swim_messages_throttled_total", "Messages dropped because the sender ran out of its outgoing budget.", &self.messages_throttled),
+                ("swim_compression_saved_bytes_total", "Bytes saved by compressing large messages.", &self.compression_saved_bytes),
+                ("swim_nodes_added_total", "Nodes added by the request router.", &self.nodes_added),
+                ("swim_requests_routed_total", "Requests sent by the request router.", &self.requests_routed),
+            ];
+            for (name, help, counter) in counters.iter() {
+                let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, counter.get());
+            }
+
+            let _ = writeln!(out, "# HELP swim_inbox_depth Messages waiting in node inboxes.\n# TYPE swim_inbox_depth gauge\nswim_inbox_depth {}",
+                             self.inbox_depth.get());
+
+            let histogram = &self.message_size_bytes;
+            let _ = writeln!(out, "# HELP swim_message_size_bytes Estimated size of sent messages.\n# TYPE swim_message_size_bytes histogram");
+            let mut cumulative = 0;
+            for (bucket, count) in histogram.buckets.iter().zip(histogram.counts.iter()) {
+                cumulative += count.load(Ordering::Relaxed);
+                let _ = writeln!(out, "swim_message_size_bytes_bucket{{le=\"{}\"}} {}", bucket, cumulative);
+            }
+            let _ = writeln!(out, "swim_message_size_bytes_bucket{{le=\"+Inf\"}} {}", histogram.count());
+            let _ = writeln!(out, "swim_message_size_bytes_sum {}", histogram.sum());
+            let _ = writeln!(out, "swim_message_size_bytes_count {}", histogram.count());
+            out
+        }
+    }
+
+    pub fn metrics() -> &'static Metrics {
+        static METRICS: OnceLock<Metrics> = OnceLock::new();
+        METRICS.get_or_init(Metrics::new)
+    }
+
+    /// Returns all metrics in the Prometheus text exposition format.
+    pub fn export_prometheus() -> String {
+        metrics().render()
+    }
+
+    /// Serves the metrics over HTTP at `/metrics` on the given address from a background thread and returns
+    /// the bound address. Other paths are not found.
+    pub fn serve<A: ToSocketAddrs>(address: A) -> std::io::Result<SocketAddr> {
+        let listener = TcpListener::bind(address)?;
+        let local_address = listener.local_addr()?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(err) => {
+                        log!("Failed to accept metrics connection - {:?}", err);
+                        continue;
+                    }
+                };
+                let mut request_line = String::new();
+                if let Ok(s) = stream.try_clone() {
+                    let _ = BufReader::new(s).read_line(&mut request_line);
+                }
+                let response = match request_line.split_whitespace().nth(1) {
+                    Some("/metrics") => {
+                        let body = export_prometheus();
+                        format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                body.len(), body)
+                    }
+                    _ => String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+                };
+                stream.write_all(response.as_bytes())
+                    .unwrap_or_else(|err| log!("Failed to write metrics response - {:?}", err));
+            }
+        });
+        Ok(local_address)
+    }
+}
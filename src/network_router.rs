@@ -1,48 +1,283 @@
-use std::collections::{HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::Not;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, mpsc, Mutex};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
 use crate::connection::swim_node::{ConnectionRegistry};
-use crate::member_node::swim_node::{DefaultMemberNode, MemberNode};
+use crate::member_node::swim_node::{DefaultMemberNode, MemberNode, MemberNodeDetails, MemberNodeHandle, MemberNodeState, NodeConfig};
+use crate::election::swim_node::Leadership;
+use crate::event::swim_node::NodeEvent;
+use crate::log;
 use crate::message::swim_node::Message;
+use crate::metrics::swim_node::metrics;
+use crate::query::swim_node::{QueryEvent, QueryHandler, QueryParams};
+use crate::rpc::swim_node::{Envelope, RequestHandler};
 
-pub trait NodeRequestRouter {
+/// Runs a simulated cluster, the operations on it are split by concern into the supertraits.
+pub trait NodeRequestRouter: FaultInjection + ClusterMessaging + ClusterInsight {
     fn start(&mut self);
 
+    fn add(&mut self, host: u16);
+
     fn send(&mut self, from: u16, to: u16);
 
     fn shut_down(&self);
 }
 
-type Routes<T> = HashMap<u16, Arc<Mutex<T>>>;
+/// Failures and network conditions injected into the simulated cluster.
+pub trait FaultInjection {
+    /// Makes the node stop responding to pings, so the rest of the cluster detects it as failed.
+    fn fail(&mut self, host: u16);
+
+    /// Makes a failed node respond to pings again.
+    fn revive(&mut self, host: u16);
+
+    /// Stops the threads of the node without it leaving the cluster, so its messages are lost from now on.
+    /// The node is unregistered from the network until it's restarted.
+    fn crash(&mut self, host: u16);
+
+    /// Makes the node stop processing messages and protocol periods while they keep arriving.
+    fn pause(&mut self, host: u16);
+
+    /// Resumes a paused node, which catches up on the messages received in the meantime.
+    fn unpause(&mut self, host: u16);
+
+    /// Replaces the node, crashing it first if it's still running, with a fresh one of the next incarnation.
+    fn restart(&mut self, host: u16);
+
+    /// Starts a new protocol period on every node without waiting for their timers.
+    fn tick(&self);
+
+    /// Splits the network, so nodes from different groups can't reach each other.
+    fn partition(&mut self, groups: Vec<Vec<u16>>);
+
+    fn heal(&mut self);
+}
+
+/// User events, queries and requests sent through the nodes of the cluster.
+pub trait ClusterMessaging {
+    /// Broadcasts a user event from the node to the whole cluster.
+    fn broadcast_event(&mut self, from: u16, name: &str, payload: Vec<u8>);
+
+    fn set_tags(&mut self, host: u16, tags: BTreeMap<String, String>);
+
+    /// Registers the handler answering the queries with the given name on the node.
+    fn handle_query(&mut self, host: u16, name: &str, handler: QueryHandler);
+
+    /// Registers the handler answering the requests of the given kind on the node.
+    fn handle_requests(&mut self, host: u16, kind: &str, handler: RequestHandler);
+
+    /// Sends a request between two nodes, the stream yields the response body if it arrives within the timeout.
+    fn request(&mut self, from: u16, to: u16, kind: &str, body: Vec<u8>, timeout: Duration) -> Receiver<Vec<u8>>;
+
+    /// Runs a query from the node across the cluster, the stream is closed right away if the node isn't running.
+    fn query(&mut self, from: u16, name: &str, payload: Vec<u8>, params: QueryParams) -> Receiver<QueryEvent>;
+}
+
+/// What the nodes of the cluster know about it.
+pub trait ClusterInsight {
+    fn details(&self, host: u16) -> Option<MemberNodeDetails>;
+
+    fn hosts(&self) -> Vec<u16>;
+
+    /// The leader the node follows, `None` if it's crashed or doesn't take part in the election.
+    fn leadership(&self, host: u16) -> Option<Leadership>;
+
+    /// Round-trip time between two members as estimated by the node, `None` if it's crashed or
+    /// doesn't know the coordinates of the members.
+    fn estimate_rtt(&self, host: u16, from: u16, to: u16) -> Option<Duration>;
+
+    /// Returns a stream of the events of all nodes, including the ones added later, tagged by the node host.
+    fn subscribe(&mut self) -> Receiver<(u16, NodeEvent)>;
+}
+
+type Routes<T> = HashMap<u16, T>;
 
 pub struct DefaultNodeRequestRouter<T> where T : MemberNode {
     routes: Routes<T>,
     connection_factory: Arc<Mutex<dyn ConnectionRegistry>>,
     node_factory: Box<dyn NodeFactory<T>>,
+    event_feeds: Vec<Sender<(u16, NodeEvent)>>,
+    crashed: HashSet<u16>,
+    incarnations: HashMap<u16, u32>,
 }
 
 impl <T> NodeRequestRouter for DefaultNodeRequestRouter<T> where T : MemberNode {
     fn start(&mut self) {
         if self.routes.is_empty() {
-            self.add_node(1)
+            self.add_node(1, 0)
+        }
+    }
+
+    fn add(&mut self, host: u16) {
+        if self.routes.contains_key(&host).not() {
+            self.add_node(host, 0)
         }
     }
 
     fn send(&mut self, from: u16, to: u16) {
-        if self.routes.contains_key(&from).not() {
-            self.add_node(from)
+        self.add(from);
+        if self.crashed.contains(&from) {
+            log!("Node {} is crashed and can't send requests", from);
+            return;
         }
-        let from_node_details = self.routes.get(&from).unwrap().lock().unwrap().serialize_host_details();
-        self.connection_factory.lock().unwrap().send_to(to, Message::Request(from_node_details, format!("hello from {}", from)))
+        let from_node_details = match self.routes.get(&from).unwrap().serialize_host_details() {
+            Some(details) => details,
+            None => {
+                log!("Node {} is not running and can't send requests", from);
+                return;
+            }
+        };
+        metrics().requests_routed.inc();
+        let hello = Envelope::new("hello", format!("hello from {}", from).into_bytes());
+        self.connection_factory.lock().unwrap().send_from(from, to, Message::Request(from_node_details, hello))
     }
 
     fn shut_down(&self) {
-        for node in self.routes.values() {
-            self.connection_factory.lock().unwrap().send_to(node.lock().unwrap().host(), Message::Shutdown());
+        for node in self.routes.values().filter(|n| self.crashed.contains(&n.host()).not()) {
+            self.connection_factory.lock().unwrap().send_to(node.host(), Message::Shutdown());
+        }
+    }
+}
+
+impl <T> FaultInjection for DefaultNodeRequestRouter<T> where T : MemberNode {
+    fn fail(&mut self, host: u16) {
+        if let Some(node) = self.routes.get(&host) {
+            node.change_state(MemberNodeState::Failed);
+        }
+    }
+
+    fn revive(&mut self, host: u16) {
+        if let Some(node) = self.routes.get(&host) {
+            node.change_state(MemberNodeState::Alive);
+        }
+    }
+
+    fn crash(&mut self, host: u16) {
+        if self.routes.contains_key(&host) && self.crashed.insert(host) {
+            let mut connection_factory = self.connection_factory.lock().unwrap();
+            connection_factory.send_to(host, Message::Shutdown());
+            connection_factory.remove_connection(host);
+            log!("Node {} has crashed", host);
+        }
+    }
+
+    fn pause(&mut self, host: u16) {
+        if self.is_running(host) {
+            self.connection_factory.lock().unwrap().send_to(host, Message::Pause());
+        }
+    }
+
+    fn unpause(&mut self, host: u16) {
+        if self.is_running(host) {
+            self.connection_factory.lock().unwrap().send_to(host, Message::Unpause());
+        }
+    }
+
+    fn restart(&mut self, host: u16) {
+        if self.routes.contains_key(&host).not() {
+            return;
+        }
+        self.crash(host);
+        let incarnation = self.incarnations.get(&host).map_or(1, |i| i + 1);
+        self.crashed.remove(&host);
+        self.add_node(host, incarnation);
+    }
+
+    fn tick(&self) {
+        for host in self.routes.keys().filter(|h| self.crashed.contains(h).not()) {
+            self.connection_factory.lock().unwrap().send_to(*host, Message::Tick());
+        }
+    }
+
+    fn partition(&mut self, groups: Vec<Vec<u16>>) {
+        self.connection_factory.lock().unwrap().partition(groups);
+    }
+
+    fn heal(&mut self) {
+        self.connection_factory.lock().unwrap().heal();
+    }
+}
+
+impl <T> ClusterMessaging for DefaultNodeRequestRouter<T> where T : MemberNode {
+    fn broadcast_event(&mut self, from: u16, name: &str, payload: Vec<u8>) {
+        if self.is_running(from) {
+            self.routes.get(&from).unwrap().broadcast_event(name, payload);
+        }
+    }
+
+    fn set_tags(&mut self, host: u16, tags: BTreeMap<String, String>) {
+        if self.is_running(host) {
+            self.routes.get(&host).unwrap().set_tags(tags);
+        }
+    }
+
+    fn handle_query(&mut self, host: u16, name: &str, handler: QueryHandler) {
+        if self.is_running(host) {
+            self.routes.get(&host).unwrap().handle_query(name, handler);
+        }
+    }
+
+    fn handle_requests(&mut self, host: u16, kind: &str, handler: RequestHandler) {
+        if self.is_running(host) {
+            self.routes.get(&host).unwrap().handle_requests(kind, handler);
+        }
+    }
+
+    fn request(&mut self, from: u16, to: u16, kind: &str, body: Vec<u8>, timeout: Duration) -> Receiver<Vec<u8>> {
+        if self.is_running(from) {
+            metrics().requests_routed.inc();
+            self.routes.get(&from).unwrap().request(to, kind, body, timeout)
+        } else {
+            mpsc::channel().1
+        }
+    }
+
+    fn query(&mut self, from: u16, name: &str, payload: Vec<u8>, params: QueryParams) -> Receiver<QueryEvent> {
+        if self.is_running(from) {
+            self.routes.get(&from).unwrap().query(name, payload, params)
+        } else {
+            mpsc::channel().1
         }
     }
 }
 
+impl <T> ClusterInsight for DefaultNodeRequestRouter<T> where T : MemberNode {
+    fn details(&self, host: u16) -> Option<MemberNodeDetails> {
+        self.routes.get(&host)
+            .filter(|_| self.crashed.contains(&host).not())
+            .and_then(|node| node.serialize_host_details())
+    }
+
+    fn hosts(&self) -> Vec<u16> {
+        let mut hosts: Vec<u16> = self.routes.keys().cloned().collect();
+        hosts.sort_unstable();
+        hosts
+    }
+
+    fn leadership(&self, host: u16) -> Option<Leadership> {
+        self.routes.get(&host)
+            .filter(|_| self.crashed.contains(&host).not())
+            .and_then(|node| node.leadership())
+    }
+
+    fn estimate_rtt(&self, host: u16, from: u16, to: u16) -> Option<Duration> {
+        self.routes.get(&host)
+            .filter(|_| self.crashed.contains(&host).not())
+            .and_then(|node| node.estimate_rtt(from, to))
+    }
+
+    fn subscribe(&mut self) -> Receiver<(u16, NodeEvent)> {
+        let (sender, receiver) = mpsc::channel();
+        for (host, node) in self.routes.iter() {
+            forward_events(*host, node, sender.clone());
+        }
+        self.event_feeds.push(sender);
+        receiver
+    }
+}
+
 impl <T> DefaultNodeRequestRouter<T> where T : MemberNode {
     pub fn new(node_factory: Box<dyn NodeFactory<T>>,
                   connection_registry: Arc<Mutex<dyn ConnectionRegistry + Send>>) -> Box<DefaultNodeRequestRouter<T>> {
@@ -50,25 +285,63 @@ impl <T> DefaultNodeRequestRouter<T> where T : MemberNode {
             routes: Routes::<T>::new(),
             connection_factory: connection_registry,
             node_factory,
+            event_feeds: Vec::new(),
+            crashed: HashSet::new(),
+            incarnations: HashMap::new(),
         })
     }
 
-    fn add_node(&mut self, host: u16) {
-        self.routes.insert(host, self.node_factory.create(host, Arc::clone(&self.connection_factory)));
+    pub fn node(&self, host: u16) -> Option<&T> {
+        self.routes.get(&host)
+    }
+
+    fn is_running(&self, host: u16) -> bool {
+        self.routes.contains_key(&host) && self.crashed.contains(&host).not()
+    }
 
-        println!("Node {} has been added", host);
+    fn add_node(&mut self, host: u16, incarnation: u32) {
+        let node = self.node_factory.create(host, incarnation, Arc::clone(&self.connection_factory));
+        for feed in self.event_feeds.iter() {
+            forward_events(host, &node, feed.clone());
+        }
+        self.routes.insert(host, node);
+        self.incarnations.insert(host, incarnation);
+        metrics().nodes_added.inc();
+
+        log!("Node {} has been added with incarnation {}", host, incarnation);
     }
 }
 
+/// Forwards the events of the node to the feed from a background thread until either of them is gone.
+fn forward_events<T: MemberNode>(host: u16, node: &T, feed: Sender<(u16, NodeEvent)>) {
+    let events = node.subscribe();
+    thread::spawn(move || {
+        for event in events {
+            if feed.send((host, event)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
 pub trait NodeFactory<T>
     where T: MemberNode {
-    fn create(&self, host: u16, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> Arc<Mutex<T>>;
+    fn create(&self, host: u16, incarnation: u32, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> T;
+}
+
+#[derive(Default)]
+pub struct DefaultNodeFactory {
+    config: NodeConfig,
 }
 
-pub struct DefaultNodeFactory;
+impl DefaultNodeFactory {
+    pub fn with_config(config: NodeConfig) -> DefaultNodeFactory {
+        DefaultNodeFactory { config }
+    }
+}
 
-impl NodeFactory<DefaultMemberNode> for DefaultNodeFactory {
-    fn create(&self, host: u16, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> Arc<Mutex<DefaultMemberNode>> {
-        DefaultMemberNode::new(host, connection)
+impl NodeFactory<MemberNodeHandle> for DefaultNodeFactory {
+    fn create(&self, host: u16, incarnation: u32, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> MemberNodeHandle {
+        DefaultMemberNode::start_with_incarnation(host, incarnation, self.config.clone(), connection)
     }
 }
\ No newline at end of file
@@ -0,0 +1,127 @@
+pub mod swim_node {
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+    use std::time::{Duration, Instant};
+    use crate::member_node::swim_node::{MemberNodeState, MemberNodesRegistry};
+    use crate::rendezvous::swim_node::ZONE_TAG;
+
+    #[derive(Clone, Debug)]
+    pub struct PartitionConfig {
+        /// Failures declared within this window are considered correlated.
+        pub window: Duration,
+        /// Fraction of the members, or of the members of a zone, which have to fail within the window.
+        pub threshold: f64,
+        /// Fewer correlated failures than this are never taken for a partition, e.g. one member of three.
+        pub min_failures: usize,
+        /// Keeps suspected members suspected instead of declaring them failed while a partition is suspected,
+        /// so the view of the node doesn't shrink to its side of the partition.
+        pub throttle: bool,
+    }
+
+    impl Default for PartitionConfig {
+        fn default() -> Self {
+            PartitionConfig {
+                window: Duration::from_secs(10),
+                threshold: 0.3,
+                min_failures: 2,
+                throttle: false,
+            }
+        }
+    }
+
+    /// Members which failed together, and the zones in which the threshold of members failed.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct PartitionSuspicion {
+        pub members: BTreeSet<u16>,
+        pub zones: BTreeSet<String>,
+    }
+
+    /// Tells a network partition from ordinary failures: a large fraction of the cluster, or of a zone,
+    /// failing within a short window is more likely the network between this node and them than
+    /// that many crashes at once. The suspicion lasts until none of its members is failed any more,
+    /// because they are back or reaped.
+    pub struct PartitionDetector {
+        config: PartitionConfig,
+        failed_at: HashMap<u16, Instant>,
+        suspicion: Option<PartitionSuspicion>,
+    }
+
+    impl PartitionDetector {
+        pub fn new(config: PartitionConfig) -> PartitionDetector {
+            PartitionDetector { config, failed_at: HashMap::new(), suspicion: None }
+        }
+
+        pub fn suspicion(&self) -> Option<&PartitionSuspicion> {
+            self.suspicion.as_ref()
+        }
+
+        /// True if the node should hold off declaring suspected members failed.
+        pub fn throttles(&self) -> bool {
+            self.config.throttle && self.suspicion.is_some()
+        }
+
+        /// Records the members which failed since the last update and re-evaluates the suspicion. Returns
+        /// the suspicion if a partition is newly suspected or more members joined it.
+        pub fn update(&mut self, members: &MemberNodesRegistry, now: Instant) -> Option<PartitionSuspicion> {
+            let failed = |host: u16| members.get_state_for(host) == Some(&MemberNodeState::Failed);
+            self.failed_at.retain(|host, _| failed(*host));
+            for host in members.hosts().into_iter().filter(|h| failed(*h)) {
+                self.failed_at.entry(host).or_insert(now);
+            }
+            if let Some(suspicion) = self.suspicion.as_mut() {
+                suspicion.members.retain(|host| failed(*host));
+                if suspicion.members.is_empty() {
+                    self.suspicion = None;
+                }
+            }
+
+            let recent: BTreeSet<u16> = self.failed_at.iter()
+                .filter(|(_, at)| now.duration_since(**at) <= self.config.window)
+                .map(|(host, _)| *host)
+                .collect();
+            let zones = self.failed_zones(members, &recent);
+            if recent.len() < self.config.min_failures {
+                return None;
+            }
+            let known = 1 + members.hosts().into_iter()
+                .filter(|h| members.get_state_for(*h) != Some(&MemberNodeState::Left))
+                .count();
+            if !self.exceeds(recent.len(), known) && zones.is_empty() {
+                return None;
+            }
+
+            let suspicion = self.suspicion.get_or_insert_with(|| PartitionSuspicion {
+                members: BTreeSet::new(),
+                zones: BTreeSet::new(),
+            });
+            let size = (suspicion.members.len(), suspicion.zones.len());
+            suspicion.members.extend(recent);
+            suspicion.zones.extend(zones);
+            if (suspicion.members.len(), suspicion.zones.len()) == size {
+                return None;
+            }
+            Some(suspicion.clone())
+        }
+
+        /// The zones in which the threshold of the members, and at least `min_failures`, failed recently.
+        fn failed_zones(&self, members: &MemberNodesRegistry, recent: &BTreeSet<u16>) -> BTreeSet<String> {
+            let mut zones: BTreeMap<&String, (usize, usize)> = BTreeMap::new();
+            for host in members.hosts() {
+                if let Some(zone) = members.get_tags_for(host).and_then(|tags| tags.get(ZONE_TAG)) {
+                    let (failed, total) = zones.entry(zone).or_default();
+                    *total += 1;
+                    if recent.contains(&host) {
+                        *failed += 1;
+                    }
+                }
+            }
+            zones.into_iter()
+                .filter(|(_, (failed, total))| *failed >= self.config.min_failures && self.exceeds(*failed, *total))
+                .map(|(zone, _)| zone.clone())
+                .collect()
+        }
+
+        fn exceeds(&self, failed: usize, total: usize) -> bool {
+            failed as f64 >= self.config.threshold * total as f64
+        }
+    }
+}
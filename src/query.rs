@@ -0,0 +1,51 @@
+pub mod swim_node {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    const DEFAULT_QUERY_TIMEOUT: u64 = 2;
+
+    /// Computes the response of a node to a query payload.
+    pub type QueryHandler = Box<dyn Fn(&[u8]) -> Vec<u8> + Send>;
+
+    /// A named request fanned out to every member of the cluster.
+    #[derive(Clone, PartialEq, Debug)]
+    pub struct Query {
+        pub id: u64,
+        pub name: String,
+        pub payload: Vec<u8>,
+        pub filter: BTreeMap<String, String>,
+    }
+
+    impl Query {
+        /// A node takes part in the query only if it has every tag of the filter with the same value.
+        pub fn matches(&self, tags: &BTreeMap<String, String>) -> bool {
+            self.filter.iter().all(|(key, value)| tags.get(key) == Some(value))
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct QueryParams {
+        /// Tags a member must have to take part in the query, all members take part if it's empty.
+        pub filter: BTreeMap<String, String>,
+        /// How long the originator collects acks and responses before the result stream is closed.
+        pub timeout: Duration,
+    }
+
+    impl Default for QueryParams {
+        fn default() -> Self {
+            QueryParams {
+                filter: BTreeMap::new(),
+                timeout: Duration::from_secs(DEFAULT_QUERY_TIMEOUT),
+            }
+        }
+    }
+
+    /// Results of a query as they arrive at the originator.
+    #[derive(Clone, PartialEq, Debug)]
+    pub enum QueryEvent {
+        /// The member received the query and matches its filter.
+        Ack(u16),
+        /// The handler of the member returned a response.
+        Response(u16, Vec<u8>),
+    }
+}
@@ -0,0 +1,75 @@
+pub mod swim_node {
+    use std::collections::HashMap;
+    use std::time::Instant;
+    use crate::message::swim_node::MessagePriority;
+
+    /// Outgoing budget of every node. A node may send a second worth of its budget in a burst.
+    #[derive(Clone, Copy, Debug)]
+    pub struct RateLimit {
+        pub messages_per_sec: f64,
+        pub bytes_per_sec: f64,
+        /// Fraction of the budget gossip can't use, it's kept for acks and probes so a gossip storm
+        /// doesn't make the members of a node suspect it.
+        pub reserve: f64,
+    }
+
+    impl Default for RateLimit {
+        fn default() -> Self {
+            RateLimit {
+                messages_per_sec: 100.0,
+                bytes_per_sec: 64.0 * 1024.0,
+                reserve: 0.25,
+            }
+        }
+    }
+
+    struct Budget {
+        messages: f64,
+        bytes: f64,
+        refilled_at: Instant,
+    }
+
+    /// Token buckets of the messages and bytes sent by each node. A message which is larger than
+    /// what's left is still sent as long as some budget is left, and the node pays it back before
+    /// it sends anything else, so large membership lists aren't blocked forever.
+    pub struct RateLimiter {
+        limit: RateLimit,
+        budgets: HashMap<u16, Budget>,
+    }
+
+    impl RateLimiter {
+        pub fn new(limit: RateLimit) -> RateLimiter {
+            RateLimiter { limit, budgets: HashMap::new() }
+        }
+
+        pub fn limit(&self) -> &RateLimit {
+            &self.limit
+        }
+
+        /// Takes a message of the given size and priority from the budget of the sender, returns `false`
+        /// if the message has to be dropped.
+        pub fn admit(&mut self, from: u16, size: usize, priority: MessagePriority, now: Instant) -> bool {
+            let limit = self.limit;
+            if priority == MessagePriority::Control {
+                return true;
+            }
+            let budget = self.budgets.entry(from).or_insert(Budget {
+                messages: limit.messages_per_sec,
+                bytes: limit.bytes_per_sec,
+                refilled_at: now,
+            });
+            let elapsed = now.saturating_duration_since(budget.refilled_at).as_secs_f64();
+            budget.messages = (budget.messages + elapsed * limit.messages_per_sec).min(limit.messages_per_sec);
+            budget.bytes = (budget.bytes + elapsed * limit.bytes_per_sec).min(limit.bytes_per_sec);
+            budget.refilled_at = now;
+
+            let reserve = if priority == MessagePriority::Gossip { limit.reserve } else { 0.0 };
+            if budget.messages < 1.0 + reserve * limit.messages_per_sec || budget.bytes <= reserve * limit.bytes_per_sec {
+                return false;
+            }
+            budget.messages -= 1.0;
+            budget.bytes -= size as f64;
+            true
+        }
+    }
+}
@@ -0,0 +1,98 @@
+pub mod swim_node {
+    use std::collections::{BTreeMap, HashSet};
+    use crate::member_node::swim_node::{MemberNodeDetails, MemberNodeState, MemberNodesRegistry};
+    use crate::ring::swim_node::hash;
+
+    /// Tag naming the failure domain of a member, e.g. an availability zone or a rack.
+    pub const ZONE_TAG: &str = "zone";
+
+    /// Rendezvous (highest random weight) hashing over a set of alive members. Every member scores
+    /// every key and the key belongs to the members with the highest scores, so nodes with the same
+    /// view agree on the owners without coordination, and removing a member only moves its own keys.
+    pub struct Rendezvous {
+        members: BTreeMap<u16, Option<String>>,
+    }
+
+    impl Rendezvous {
+        /// Members with their tags, members without a zone tag are each treated as a zone of their own.
+        pub fn new<I: IntoIterator<Item = (u16, Option<String>)>>(members: I) -> Rendezvous {
+            Rendezvous { members: members.into_iter().collect() }
+        }
+
+        /// The alive members of the registry.
+        pub fn from_registry(registry: &MemberNodesRegistry) -> Rendezvous {
+            Rendezvous::new(registry.hosts().into_iter()
+                .filter(|host| registry.get_state_for(*host) == Some(&MemberNodeState::Alive))
+                .map(|host| (host, zone_of(registry.get_tags_for(host)))))
+        }
+
+        /// The node itself and its alive members, which is the same set on every node sharing the view.
+        pub fn from_details(details: &MemberNodeDetails) -> Rendezvous {
+            let mut rendezvous = Rendezvous::from_registry(details.members());
+            rendezvous.members.insert(details.host(), zone_of(Some(details.tags())));
+            rendezvous
+        }
+
+        /// All members ordered from the highest to the lowest score for the key.
+        pub fn ranked(&self, key: &[u8]) -> Vec<u16> {
+            let mut scored: Vec<(u64, u16)> = self.members.keys()
+                .map(|host| (score(key, *host), *host))
+                .collect();
+            scored.sort_unstable_by(|a, b| b.cmp(a));
+            scored.into_iter().map(|(_, host)| host).collect()
+        }
+
+        pub fn owner(&self, key: &[u8]) -> Option<u16> {
+            self.ranked(key).first().copied()
+        }
+
+        /// Up to `n` members for the replicas of the key, the first one being its primary owner. Replicas go
+        /// to distinct zones in the order of their scores, and share zones only when there are fewer zones than `n`.
+        pub fn replicas(&self, key: &[u8], n: usize) -> Vec<u16> {
+            let ranked = self.ranked(key);
+            let mut zones = HashSet::new();
+            let mut replicas: Vec<u16> = Vec::with_capacity(n);
+            for host in ranked.iter() {
+                if replicas.len() == n {
+                    return replicas;
+                }
+                let distinct = match &self.members[host] {
+                    Some(zone) => zones.insert(zone.as_str()),
+                    None => true,
+                };
+                if distinct {
+                    replicas.push(*host);
+                }
+            }
+            for host in ranked {
+                if replicas.len() == n {
+                    break;
+                }
+                if !replicas.contains(&host) {
+                    replicas.push(host);
+                }
+            }
+            replicas
+        }
+
+        pub fn zone_of(&self, host: u16) -> Option<&str> {
+            self.members.get(&host).and_then(|zone| zone.as_deref())
+        }
+
+        pub fn len(&self) -> usize {
+            self.members.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.members.is_empty()
+        }
+    }
+
+    fn zone_of(tags: Option<&BTreeMap<String, String>>) -> Option<String> {
+        tags.and_then(|t| t.get(ZONE_TAG)).cloned()
+    }
+
+    fn score(key: &[u8], host: u16) -> u64 {
+        hash(&[key, &host.to_be_bytes()[..]].concat())
+    }
+}
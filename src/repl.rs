@@ -0,0 +1,51 @@
+pub mod swim_node {
+    use std::io::{BufRead, Write};
+    use crate::network_router::NodeRequestRouter;
+    use crate::scenario::swim_node::{execute, parse_command};
+
+    const HELP: &str = "Commands:
+  add <host>                  create a node
+  send <from> <to>            send a request, creating the sender if needed
+  kill <host>                 make the node stop responding
+  revive <host>               make a killed node respond again
+  crash <host>                stop the node without leaving the cluster
+  pause <host>                stop processing messages until unpaused
+  unpause <host>              resume a paused node
+  restart <host>              replace the node with its next incarnation
+  partition 1,2 | 3,4         split the network into groups
+  heal                        remove all partitions
+  event <host> <name> [text]  broadcast a user event from the node
+  members <host>              show the membership list of the node
+  tick                        start a new protocol period on every node
+  wait <duration>             sleep, e.g. 500ms or 2s
+  expect [within <duration>] <host> sees <member> alive|suspected|failed
+  expect <host> lacks <member> | expect <host> knows <count>
+  expect <host> follows <leader> | expect <host> leaderless
+  help                        show this text
+  quit                        shut down the network and exit";
+
+    /// Reads commands line by line and runs them against the router until `quit` or the end of input.
+    pub fn run_repl<R: BufRead, W: Write>(input: R, mut output: W, router: &mut dyn NodeRequestRouter) -> std::io::Result<()> {
+        writeln!(output, "Type 'help' to see the commands")?;
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            match line {
+                "" => {}
+                "help" => writeln!(output, "{}", HELP)?,
+                "quit" | "exit" => break,
+                _ => match parse_command(line).and_then(|command| execute(&command, router)) {
+                    Ok(Some(text)) => writeln!(output, "{}", text)?,
+                    Ok(None) => writeln!(output, "ok")?,
+                    Err(err) => writeln!(output, "error: {}", err)?,
+                },
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        writeln!(output)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+pub mod swim_node {
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::{Arc, RwLock};
+    use std::thread;
+    use crate::event::swim_node::NodeEvent;
+    use crate::member_node::swim_node::{MemberNode, MemberNodeState};
+
+    /// Tag holding the relative capacity of a member, members without it have a weight of 1.
+    pub const WEIGHT_TAG: &str = "weight";
+
+    /// A ring shared with the thread keeping it up to date.
+    pub type SharedRing = Arc<RwLock<HashRing>>;
+
+    /// Consistent hash ring of the members of the cluster. Each member owns `virtual_nodes * weight`
+    /// points on the ring, and a key belongs to the members owning the first points at or after its hash.
+    pub struct HashRing {
+        virtual_nodes: usize,
+        ring: BTreeMap<u64, u16>,
+        members: BTreeMap<u16, u32>,
+        weights: HashMap<u16, u32>,
+    }
+
+    impl HashRing {
+        pub fn new(virtual_nodes: usize) -> HashRing {
+            HashRing {
+                virtual_nodes,
+                ring: BTreeMap::new(),
+                members: BTreeMap::new(),
+                weights: HashMap::new(),
+            }
+        }
+
+        /// Builds a ring of the node and its members and keeps it up to date from the node events
+        /// on a background thread, until the node shuts down. Returns `None` if the node is not running.
+        pub fn follow<T: MemberNode>(node: &T, virtual_nodes: usize) -> Option<SharedRing> {
+            let mut ring = HashRing::new(virtual_nodes);
+            let details = node.serialize_host_details()?;
+            ring.add(details.host(), weight_of(details.tags()));
+            let ring = Arc::new(RwLock::new(ring));
+            let events = node.subscribe();
+            let shared = Arc::clone(&ring);
+            thread::spawn(move || {
+                for event in events {
+                    shared.write().unwrap().apply(&event);
+                }
+            });
+            Some(ring)
+        }
+
+        /// Updates the ring from a membership event. Suspected members keep their keys, so a
+        /// suspicion which is refuted doesn't move keys back and forth, while degraded members hand theirs over.
+        pub fn apply(&mut self, event: &NodeEvent) {
+            match event {
+                NodeEvent::MemberStateChanged(host, MemberNodeState::Alive)
+                | NodeEvent::MemberStateChanged(host, MemberNodeState::Suspected) => {
+                    if !self.members.contains_key(host) {
+                        let weight = self.weights.get(host).copied().unwrap_or(1);
+                        self.add(*host, weight);
+                    }
+                }
+                NodeEvent::MemberStateChanged(host, MemberNodeState::Failed)
+                | NodeEvent::MemberStateChanged(host, MemberNodeState::Left)
+                | NodeEvent::MemberStateChanged(host, MemberNodeState::Degraded)
+                | NodeEvent::MemberRemoved(host) => {
+                    self.remove(*host);
+                }
+                NodeEvent::MemberTagsChanged(host, tags) => {
+                    let weight = weight_of(tags);
+                    self.weights.insert(*host, weight);
+                    if self.members.get(host).is_some_and(|w| *w != weight) {
+                        self.add(*host, weight);
+                    }
+                }
+                NodeEvent::UserEvent(_) | NodeEvent::LeaderChanged(_) | NodeEvent::PartitionSuspected(_) => {}
+            }
+        }
+
+        /// Adds the member, or changes its weight if it's already on the ring.
+        pub fn add(&mut self, host: u16, weight: u32) {
+            self.remove(host);
+            for i in 0..self.virtual_nodes * weight.max(1) as usize {
+                self.ring.insert(hash(format!("{}#{}", host, i).as_bytes()), host);
+            }
+            self.members.insert(host, weight);
+        }
+
+        pub fn remove(&mut self, host: u16) {
+            if self.members.remove(&host).is_some() {
+                self.ring.retain(|_, h| *h != host);
+            }
+        }
+
+        /// The first `n` distinct members clockwise from the key, the first one being its primary owner.
+        pub fn owners(&self, key: &[u8], n: usize) -> Vec<u16> {
+            let n = n.min(self.members.len());
+            let start = hash(key);
+            let mut owners = Vec::with_capacity(n);
+            for host in self.ring.range(start..).chain(self.ring.range(..start)).map(|(_, h)| *h) {
+                if owners.len() == n {
+                    break;
+                }
+                if !owners.contains(&host) {
+                    owners.push(host);
+                }
+            }
+            owners
+        }
+
+        pub fn owner(&self, key: &[u8]) -> Option<u16> {
+            self.owners(key, 1).first().copied()
+        }
+
+        /// Members on the ring in ascending order.
+        pub fn members(&self) -> Vec<u16> {
+            self.members.keys().cloned().collect()
+        }
+
+        pub fn len(&self) -> usize {
+            self.members.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.members.is_empty()
+        }
+    }
+
+    fn weight_of(tags: &BTreeMap<String, String>) -> u32 {
+        tags.get(WEIGHT_TAG).and_then(|w| w.parse().ok()).unwrap_or(1)
+    }
+
+    /// FNV-1a followed by the splitmix64 finalizer, which spreads similar keys over the whole ring.
+    pub(crate) fn hash(bytes: &[u8]) -> u64 {
+        let mut hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3));
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^ (hash >> 31)
+    }
+}
@@ -0,0 +1,120 @@
+pub mod swim_node {
+    use std::convert::TryInto;
+    use std::error::Error;
+    use std::fmt::{Display, Formatter};
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Duration;
+    use crate::log;
+    use crate::network_router::NodeRequestRouter;
+
+    /// Computes the response body of a node to a request body from the given host.
+    pub type RequestHandler = Box<dyn Fn(u16, &[u8]) -> Vec<u8> + Send>;
+
+    /// Application payload of a direct request or its response. Handlers are registered per `kind`,
+    /// and a response carries the `id` of its request. Requests with id 0 have no caller waiting for the response.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct Envelope {
+        pub id: u64,
+        pub kind: String,
+        pub body: Vec<u8>,
+    }
+
+    impl Envelope {
+        pub fn new(kind: &str, body: Vec<u8>) -> Envelope {
+            Envelope { id: 0, kind: String::from(kind), body }
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub struct CodecError(pub String);
+
+    impl Display for CodecError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "failed to decode payload - {}", self.0)
+        }
+    }
+
+    impl Error for CodecError {}
+
+    /// Conversion of application values to and from the bytes sent between nodes.
+    pub trait Codec: Sized {
+        fn encode(&self) -> Vec<u8>;
+
+        fn decode(bytes: &[u8]) -> Result<Self, CodecError>;
+    }
+
+    impl Codec for Vec<u8> {
+        fn encode(&self) -> Vec<u8> {
+            self.clone()
+        }
+
+        fn decode(bytes: &[u8]) -> Result<Self, CodecError> {
+            Ok(bytes.to_vec())
+        }
+    }
+
+    impl Codec for String {
+        fn encode(&self) -> Vec<u8> {
+            self.as_bytes().to_vec()
+        }
+
+        fn decode(bytes: &[u8]) -> Result<Self, CodecError> {
+            String::from_utf8(bytes.to_vec()).map_err(|err| CodecError(err.to_string()))
+        }
+    }
+
+    impl Codec for u64 {
+        fn encode(&self) -> Vec<u8> {
+            self.to_be_bytes().to_vec()
+        }
+
+        fn decode(bytes: &[u8]) -> Result<Self, CodecError> {
+            let bytes: [u8; 8] = bytes.try_into().map_err(|_| CodecError(format!("expected 8 bytes, got {}", bytes.len())))?;
+            Ok(u64::from_be_bytes(bytes))
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum RpcError {
+        /// No response arrived in time, e.g. the target is down, partitioned away or unknown.
+        Timeout,
+        Codec(CodecError),
+    }
+
+    impl Display for RpcError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                RpcError::Timeout => write!(f, "request timed out"),
+                RpcError::Codec(err) => write!(f, "{}", err),
+            }
+        }
+    }
+
+    impl Error for RpcError {}
+
+    /// Wraps a typed function into a handler. Requests which can't be decoded get an empty response.
+    pub fn typed_handler<Req, Resp, F>(handler: F) -> RequestHandler
+        where Req: Codec,
+              Resp: Codec,
+              F: Fn(u16, Req) -> Resp + Send + 'static {
+        Box::new(move |from, body| match Req::decode(body) {
+            Ok(request) => handler(from, request).encode(),
+            Err(err) => {
+                log!("Rejected request from Node {} - {}", from, err);
+                Vec::new()
+            }
+        })
+    }
+
+    /// Sends a typed request from one node to another and waits for the typed response.
+    pub fn request<Req, Resp>(router: &mut dyn NodeRequestRouter, from: u16, to: u16, kind: &str, request: &Req,
+                              timeout: Duration) -> Result<Resp, RpcError>
+        where Req: Codec,
+              Resp: Codec {
+        let responses = router.request(from, to, kind, request.encode(), timeout);
+        match responses.recv_timeout(timeout) {
+            Ok(body) => Resp::decode(&body).map_err(RpcError::Codec),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => Err(RpcError::Timeout),
+        }
+    }
+}
@@ -0,0 +1,328 @@
+pub mod swim_node {
+    use std::error::Error;
+    use std::fmt::{Display, Formatter};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::election::swim_node::ElectionConfig;
+    use crate::member_node::swim_node::{MemberNodeState, NodeConfig};
+    use crate::partition::swim_node::PartitionConfig;
+    use crate::network_router::NodeRequestRouter;
+
+    const EXPECT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// A single step of a scenario.
+    #[derive(Clone, PartialEq, Debug)]
+    pub enum Command {
+        /// `add <host>` - creates a node.
+        Add(u16),
+        /// `send <from> <to>` - sends a request, creating the sender if it doesn't exist.
+        Send(u16, u16),
+        /// `kill <host>` - makes the node stop responding.
+        Kill(u16),
+        /// `revive <host>` - makes a killed node respond again.
+        Revive(u16),
+        /// `crash <host>` - stops the node without it leaving the cluster.
+        Crash(u16),
+        /// `pause <host>` - makes the node stop processing messages until it's unpaused.
+        Pause(u16),
+        /// `unpause <host>` - resumes a paused node.
+        Unpause(u16),
+        /// `restart <host>` - replaces the node with a fresh one of the next incarnation.
+        Restart(u16),
+        /// `event <host> <name> [payload]` - broadcasts a user event from the node.
+        Event(u16, String, String),
+        /// `members <host>` - prints the membership list of the node.
+        Members(u16),
+        /// `tick` - starts a new protocol period on every node.
+        Tick,
+        /// `partition 1,2 | 3,4` - splits the network into groups.
+        Partition(Vec<Vec<u16>>),
+        /// `heal` - removes all partitions.
+        Heal,
+        /// `wait <duration>` - sleeps, e.g. `wait 500ms` or `wait 2s`.
+        Wait(Duration),
+        /// `expect [within <duration>] <assertion>` - fails the scenario unless the assertion holds,
+        /// retrying until the deadline if one is given.
+        Expect(Assertion, Option<Duration>),
+    }
+
+    /// A check of the membership list or the leader of a node.
+    #[derive(Clone, PartialEq, Debug)]
+    pub enum Assertion {
+        /// `<host> sees <member> alive|suspected|failed`
+        Sees(u16, u16, MemberNodeState),
+        /// `<host> lacks <member>`
+        Lacks(u16, u16),
+        /// `<host> knows <count>`
+        Knows(u16, usize),
+        /// `<host> follows <leader>`
+        Follows(u16, u16),
+        /// `<host> leaderless`
+        Leaderless(u16),
+    }
+
+    #[derive(Debug)]
+    pub struct ScenarioError {
+        pub line: usize,
+        pub message: String,
+    }
+
+    impl Display for ScenarioError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+
+    impl Error for ScenarioError {}
+
+    /// A list of commands read from a scenario file. Lines starting with `#` are comments, and
+    /// `set ping-interval <duration>`, `set indirect-probes <count>`, `set snapshot-dir <path>`,
+    /// `set seeds 1,2`, `set tombstone-timeout <duration>`, `set reconnect-interval <duration>`,
+    /// `set election-stability <duration>`, `set election-lease <duration>`, `set partition-window <duration>`,
+    /// `set partition-threshold <fraction>` or `set partition-throttle` lines before the first command
+    /// configure the nodes of the network. Either election setting enables the election, and any partition
+    /// setting the detection of partitions.
+    pub struct Scenario {
+        config: NodeConfig,
+        commands: Vec<(usize, Command)>,
+    }
+
+    impl Scenario {
+        pub fn load<P: AsRef<Path>>(path: P) -> Result<Scenario, ScenarioError> {
+            let text = fs::read_to_string(path.as_ref())
+                .map_err(|err| ScenarioError { line: 0, message: format!("Failed to read {:?} - {}", path.as_ref(), err) })?;
+            Scenario::parse(&text)
+        }
+
+        pub fn parse(text: &str) -> Result<Scenario, ScenarioError> {
+            let mut config = NodeConfig::default();
+            let mut commands = Vec::new();
+            for (i, line) in text.lines().enumerate() {
+                let line_number = i + 1;
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let error = |message: String| ScenarioError { line: line_number, message };
+                if let Some(setting) = line.strip_prefix("set ") {
+                    if !commands.is_empty() {
+                        return Err(error(String::from("settings must precede all commands")));
+                    }
+                    apply_setting(&mut config, setting).map_err(error)?;
+                } else {
+                    commands.push((line_number, parse_command(line).map_err(error)?));
+                }
+            }
+            Ok(Scenario { config, commands })
+        }
+
+        pub fn node_config(&self) -> &NodeConfig {
+            &self.config
+        }
+
+        pub fn commands(&self) -> Vec<&Command> {
+            self.commands.iter().map(|(_, c)| c).collect()
+        }
+
+        /// Executes the commands in order and stops at the first failed one.
+        pub fn run(&self, router: &mut dyn NodeRequestRouter) -> Result<(), ScenarioError> {
+            for (line, command) in self.commands.iter() {
+                let output = execute(command, router).map_err(|message| ScenarioError { line: *line, message })?;
+                if let Some(output) = output {
+                    println!("{}", output);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn apply_setting(config: &mut NodeConfig, setting: &str) -> Result<(), String> {
+        let words: Vec<&str> = setting.split_whitespace().collect();
+        match words.as_slice() {
+            ["ping-interval", value] => config.ping_interval = parse_duration(value)?,
+            ["indirect-probes", value] => config.indirect_probes = parse_number(value)?,
+            ["snapshot-dir", path] => config.snapshot_dir = Some(PathBuf::from(path)),
+            ["seeds", hosts] => config.seeds = hosts.split(',').map(parse_host).collect::<Result<Vec<u16>, String>>()?,
+            ["tombstone-timeout", value] => config.tombstone_timeout = parse_duration(value)?,
+            ["reconnect-interval", value] => config.reconnect_interval = parse_duration(value)?,
+            ["election-stability", value] => config.election.get_or_insert_with(ElectionConfig::default).stability = parse_duration(value)?,
+            ["election-lease", value] => config.election.get_or_insert_with(ElectionConfig::default).lease = parse_duration(value)?,
+            ["partition-window", value] => config.partition.get_or_insert_with(PartitionConfig::default).window = parse_duration(value)?,
+            ["partition-threshold", value] => config.partition.get_or_insert_with(PartitionConfig::default).threshold = parse_fraction(value)?,
+            ["partition-throttle"] => config.partition.get_or_insert_with(PartitionConfig::default).throttle = true,
+            _ => return Err(format!("unknown setting '{}'", setting)),
+        }
+        Ok(())
+    }
+
+    pub fn parse_command(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["add", host] => Ok(Command::Add(parse_host(host)?)),
+            ["send", from, to] => Ok(Command::Send(parse_host(from)?, parse_host(to)?)),
+            ["kill", host] => Ok(Command::Kill(parse_host(host)?)),
+            ["revive", host] => Ok(Command::Revive(parse_host(host)?)),
+            ["crash", host] => Ok(Command::Crash(parse_host(host)?)),
+            ["pause", host] => Ok(Command::Pause(parse_host(host)?)),
+            ["unpause", host] => Ok(Command::Unpause(parse_host(host)?)),
+            ["restart", host] => Ok(Command::Restart(parse_host(host)?)),
+            ["event", host, name] => Ok(Command::Event(parse_host(host)?, String::from(*name), String::new())),
+            ["event", host, name, payload @ ..] => Ok(Command::Event(parse_host(host)?, String::from(*name), payload.join(" "))),
+            ["members", host] => Ok(Command::Members(parse_host(host)?)),
+            ["tick"] => Ok(Command::Tick),
+            ["partition", ..] => parse_partition(&line["partition".len()..]).map(Command::Partition),
+            ["heal"] => Ok(Command::Heal),
+            ["wait", duration] => Ok(Command::Wait(parse_duration(duration)?)),
+            ["expect", "within", duration, assertion @ ..] =>
+                Ok(Command::Expect(parse_assertion(assertion)?, Some(parse_duration(duration)?))),
+            ["expect", assertion @ ..] => Ok(Command::Expect(parse_assertion(assertion)?, None)),
+            _ => Err(format!("unknown command '{}'", line)),
+        }
+    }
+
+    /// Runs one command against the router. Returns the text to show for commands which print something,
+    /// or a description of the failure if the command didn't succeed.
+    pub fn execute(command: &Command, router: &mut dyn NodeRequestRouter) -> Result<Option<String>, String> {
+        match command {
+            Command::Add(host) => router.add(*host),
+            Command::Send(from, to) => router.send(*from, *to),
+            Command::Kill(host) => router.fail(*host),
+            Command::Revive(host) => router.revive(*host),
+            Command::Crash(host) => router.crash(*host),
+            Command::Pause(host) => router.pause(*host),
+            Command::Unpause(host) => router.unpause(*host),
+            Command::Restart(host) => router.restart(*host),
+            Command::Event(host, name, payload) => router.broadcast_event(*host, name, payload.clone().into_bytes()),
+            Command::Members(host) => return describe_members(*host, router).map(Some),
+            Command::Tick => router.tick(),
+            Command::Partition(groups) => router.partition(groups.clone()),
+            Command::Heal => router.heal(),
+            Command::Wait(duration) => thread::sleep(*duration),
+            Command::Expect(assertion, within) => {
+                let deadline = Instant::now() + within.unwrap_or_default();
+                loop {
+                    match assertion.check(router) {
+                        Ok(()) => break,
+                        Err(err) if Instant::now() >= deadline => return Err(err),
+                        Err(_) => thread::sleep(EXPECT_POLL_INTERVAL),
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn describe_members(host: u16, router: &dyn NodeRequestRouter) -> Result<String, String> {
+        let details = router.details(host).ok_or(format!("node {} doesn't exist", host))?;
+        let members = details.members();
+        let mut description = format!("node {} is {}, knows {} members", host, details.state(), members.len());
+        for member in members.hosts() {
+            description.push_str(&format!("\n  {} {}", member, members.get_state_for(member).unwrap()));
+        }
+        Ok(description)
+    }
+
+    impl Assertion {
+        pub fn check(&self, router: &dyn NodeRequestRouter) -> Result<(), String> {
+            let host = match self {
+                Assertion::Sees(host, _, _) | Assertion::Lacks(host, _) | Assertion::Knows(host, _) => *host,
+                Assertion::Follows(host, _) | Assertion::Leaderless(host) => return self.check_leader(*host, router),
+            };
+            let details = router.details(host).ok_or(format!("node {} doesn't exist", host))?;
+            let members = details.members();
+            match self {
+                Assertion::Sees(_, member, state) => match members.get_state_for(*member) {
+                    Some(actual) if actual == state => Ok(()),
+                    Some(actual) => Err(format!("node {} sees node {} as {}, expected {}", host, member, actual, state)),
+                    None => Err(format!("node {} doesn't know node {}, expected {}", host, member, state)),
+                },
+                Assertion::Lacks(_, member) => match members.get_state_for(*member) {
+                    Some(actual) => Err(format!("node {} sees node {} as {}, expected it to be unknown", host, member, actual)),
+                    None => Ok(()),
+                },
+                Assertion::Knows(_, count) => if members.len() == *count {
+                    Ok(())
+                } else {
+                    Err(format!("node {} knows {} members, expected {}", host, members.len(), count))
+                },
+                Assertion::Follows(..) | Assertion::Leaderless(_) => unreachable!(),
+            }
+        }
+
+        fn check_leader(&self, host: u16, router: &dyn NodeRequestRouter) -> Result<(), String> {
+            let leadership = router.leadership(host)
+                .ok_or(format!("node {} doesn't exist or takes no part in the election", host))?;
+            match (self, leadership.leader) {
+                (Assertion::Follows(_, expected), Some(leader)) if leader == *expected => Ok(()),
+                (Assertion::Leaderless(_), None) => Ok(()),
+                (_, Some(leader)) => Err(format!("node {} follows node {} in term {}", host, leader, leadership.term)),
+                (_, None) => Err(format!("node {} has no leader", host)),
+            }
+        }
+    }
+
+    fn parse_assertion(words: &[&str]) -> Result<Assertion, String> {
+        match words {
+            [host, "sees", member, state] => Ok(Assertion::Sees(parse_host(host)?, parse_host(member)?, parse_state(state)?)),
+            [host, "lacks", member] => Ok(Assertion::Lacks(parse_host(host)?, parse_host(member)?)),
+            [host, "knows", count] => Ok(Assertion::Knows(parse_host(host)?, parse_number(count)?)),
+            [host, "follows", leader] => Ok(Assertion::Follows(parse_host(host)?, parse_host(leader)?)),
+            [host, "leaderless"] => Ok(Assertion::Leaderless(parse_host(host)?)),
+            _ => Err(format!("unknown assertion '{}'", words.join(" "))),
+        }
+    }
+
+    fn parse_partition(groups: &str) -> Result<Vec<Vec<u16>>, String> {
+        let groups: Vec<Vec<u16>> = groups.split('|')
+            .map(|group| group.split(',')
+                .map(str::trim)
+                .filter(|host| !host.is_empty())
+                .map(parse_host)
+                .collect::<Result<Vec<u16>, String>>())
+            .collect::<Result<Vec<Vec<u16>>, String>>()?;
+        if groups.len() < 2 || groups.iter().any(|g| g.is_empty()) {
+            return Err(String::from("partition needs at least two non-empty groups, e.g. 'partition 1,2 | 3,4'"));
+        }
+        Ok(groups)
+    }
+
+    fn parse_state(state: &str) -> Result<MemberNodeState, String> {
+        match state {
+            "alive" => Ok(MemberNodeState::Alive),
+            "suspected" => Ok(MemberNodeState::Suspected),
+            "failed" => Ok(MemberNodeState::Failed),
+            "left" => Ok(MemberNodeState::Left),
+            "degraded" => Ok(MemberNodeState::Degraded),
+            _ => Err(format!("unknown state '{}'", state)),
+        }
+    }
+
+    fn parse_host(host: &str) -> Result<u16, String> {
+        host.parse().map_err(|_| format!("invalid host '{}'", host))
+    }
+
+    fn parse_number(number: &str) -> Result<usize, String> {
+        number.parse().map_err(|_| format!("invalid number '{}'", number))
+    }
+
+    fn parse_fraction(fraction: &str) -> Result<f64, String> {
+        match fraction.parse::<f64>() {
+            Ok(f) if (0.0..=1.0).contains(&f) => Ok(f),
+            _ => Err(format!("invalid fraction '{}'", fraction)),
+        }
+    }
+
+    /// Parses durations such as `500ms` or `2s`.
+    pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+        let invalid = || format!("invalid duration '{}'", duration);
+        if let Some(millis) = duration.strip_suffix("ms") {
+            millis.parse().map(Duration::from_millis).map_err(|_| invalid())
+        } else if let Some(secs) = duration.strip_suffix('s') {
+            secs.parse().map(Duration::from_secs).map_err(|_| invalid())
+        } else {
+            Err(invalid())
+        }
+    }
+}
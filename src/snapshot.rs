@@ -0,0 +1,128 @@
+pub mod swim_node {
+    use std::error::Error;
+    use std::fmt::{Display, Formatter};
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use crate::member_node::swim_node::{MemberNodeDetails, MemberNodeState};
+
+    const HEADER: &str = "swim-snapshot 1";
+
+    /// The membership list and incarnation of a node, persisted so it can rejoin its peers after a restart.
+    #[derive(Clone, PartialEq, Debug)]
+    pub struct Snapshot {
+        pub incarnation: u32,
+        pub members: Vec<(u16, MemberNodeState)>,
+    }
+
+    #[derive(Debug)]
+    pub enum SnapshotError {
+        Io(io::Error),
+        /// The file exists but can't be trusted, e.g. it was truncated by a crash while being written.
+        Corrupted(String),
+    }
+
+    impl Display for SnapshotError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                SnapshotError::Io(err) => write!(f, "failed to read snapshot - {}", err),
+                SnapshotError::Corrupted(reason) => write!(f, "corrupted snapshot - {}", reason),
+            }
+        }
+    }
+
+    impl Error for SnapshotError {}
+
+    impl Snapshot {
+        pub fn of(details: &MemberNodeDetails) -> Snapshot {
+            let members = details.members();
+            Snapshot {
+                incarnation: details.incarnation(),
+                members: members.hosts().into_iter()
+                    .map(|host| (host, *members.get_state_for(host).unwrap()))
+                    .collect(),
+            }
+        }
+
+        /// Members which were alive, or degraded, when the snapshot was taken.
+        pub fn alive_members(&self) -> Vec<u16> {
+            self.members.iter()
+                .filter(|(_, state)| matches!(state, MemberNodeState::Alive | MemberNodeState::Degraded))
+                .map(|(host, _)| *host)
+                .collect()
+        }
+
+        /// Snapshot file of the node in the given directory.
+        pub fn path(dir: &Path, host: u16) -> PathBuf {
+            dir.join(format!("node-{}.snapshot", host))
+        }
+
+        /// Writes the snapshot next to the target and renames it over, so a crash never leaves a partial file behind.
+        pub fn save(&self, path: &Path) -> io::Result<()> {
+            let mut body = format!("{}\nincarnation {}\n", HEADER, self.incarnation);
+            for (host, state) in self.members.iter() {
+                body.push_str(&format!("member {} {}\n", host, state));
+            }
+            let text = format!("{}checksum {:08x}\n", body, checksum(&body));
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let temporary = path.with_extension("tmp");
+            fs::write(&temporary, text)?;
+            fs::rename(&temporary, path)
+        }
+
+        pub fn load(path: &Path) -> Result<Snapshot, SnapshotError> {
+            let text = fs::read_to_string(path).map_err(SnapshotError::Io)?;
+            Snapshot::parse(&text)
+        }
+
+        pub fn parse(text: &str) -> Result<Snapshot, SnapshotError> {
+            let corrupted = |reason: &str| SnapshotError::Corrupted(String::from(reason));
+            let body_end = text.rfind("checksum ").ok_or_else(|| corrupted("missing checksum"))?;
+            let (body, trailer) = text.split_at(body_end);
+            let expected = u32::from_str_radix(trailer["checksum ".len()..].trim(), 16)
+                .map_err(|_| corrupted("invalid checksum"))?;
+            if checksum(body) != expected {
+                return Err(corrupted("checksum mismatch"));
+            }
+
+            let mut lines = body.lines();
+            if lines.next() != Some(HEADER) {
+                return Err(corrupted("unknown header"));
+            }
+            let incarnation = match lines.next().and_then(|l| l.strip_prefix("incarnation ")) {
+                Some(value) => value.parse().map_err(|_| corrupted("invalid incarnation"))?,
+                None => return Err(corrupted("missing incarnation")),
+            };
+            let mut members = Vec::new();
+            for line in lines {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                match words.as_slice() {
+                    ["member", host, state] => {
+                        let host = host.parse().map_err(|_| corrupted("invalid member host"))?;
+                        members.push((host, parse_state(state).ok_or_else(|| corrupted("invalid member state"))?));
+                    }
+                    _ => return Err(corrupted("unknown line")),
+                }
+            }
+            Ok(Snapshot { incarnation, members })
+        }
+    }
+
+    fn parse_state(state: &str) -> Option<MemberNodeState> {
+        match state {
+            "Alive" => Some(MemberNodeState::Alive),
+            "Suspected" => Some(MemberNodeState::Suspected),
+            "Failed" => Some(MemberNodeState::Failed),
+            "Left" => Some(MemberNodeState::Left),
+            "Degraded" => Some(MemberNodeState::Degraded),
+            _ => None,
+        }
+    }
+
+    /// FNV-1a hash of the snapshot body.
+    fn checksum(body: &str) -> u32 {
+        body.bytes().fold(0x811c_9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+    }
+}
@@ -1,115 +1,2104 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     mod connection_tests {
+        use crate::connection::swim_node::{ConnectionFactory, ConnectionRegistry, DropPolicy, InboxReceiver};
+        use std::time::{Duration, Instant};
+        use crate::coordinate::swim_node::Coordinate;
+        use crate::latency::swim_node::{LatencyDistribution, LatencyTopology};
+        use crate::member_node::swim_node::MemberNodeDetails;
+        use crate::message::swim_node::Message;
+        use crate::rpc::swim_node::Envelope;
+
+        #[test]
+        fn test_connection_create() {
+            let connection_factory = create_simple_connection_factory_with_receiver();
+
+            assert!(connection_factory.0.get_connection_for(1).is_some())
+        }
+
+        #[test]
+        fn test_connection_delete() {
+            let mut connection_factory = create_simple_connection_factory_with_receiver();
+            connection_factory.0.remove_connection(1);
+
+            assert!(connection_factory.0.get_connection_for(1).is_none())
+        }
+
+        #[test]
+        fn test_connection_send_and_receive() {
+            let connection_factory = create_simple_connection_factory_with_receiver();
+
+            connection_factory.0.send_to(1, Message::Shutdown());
+
+            match connection_factory.1.recv().unwrap() {
+                Message::Shutdown() => {}
+                _ => { panic!("Failed to send message from connection factory") }
+            }
+        }
+
+        #[test]
+        fn test_connection_drop_newest_when_full() {
+            let (connection_factory, receiver) = create_bounded_connection_factory_with_receiver(DropPolicy::DropNewest);
+
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"first".to_vec())));
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(3), Envelope::new("hello", b"second".to_vec())));
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(4), Envelope::new("hello", b"third".to_vec())));
+
+            assert_eq!(1, connection_factory.dropped_messages(1));
+            assert_eq!(vec![2, 3], receive_request_senders(&receiver, 2));
+        }
+
+        #[test]
+        fn test_connection_drop_oldest_when_full() {
+            let (connection_factory, receiver) = create_bounded_connection_factory_with_receiver(DropPolicy::DropOldest);
+
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"first".to_vec())));
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(3), Envelope::new("hello", b"second".to_vec())));
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(4), Envelope::new("hello", b"third".to_vec())));
+
+            assert_eq!(1, connection_factory.dropped_messages(1));
+            assert_eq!(vec![3, 4], receive_request_senders(&receiver, 2));
+        }
+
+        #[test]
+        fn test_connection_prioritize_acks_when_full() {
+            let (connection_factory, receiver) = create_bounded_connection_factory_with_receiver(DropPolicy::PrioritizeAcks);
+
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"gossip".to_vec())));
+            connection_factory.send_to(1, Message::Ping(MemberNodeDetails::new(3), None, Coordinate::new()));
+            connection_factory.send_to(1, Message::PingResponse(4, None, false, Coordinate::new()));
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(5), Envelope::new("hello", b"gossip".to_vec())));
+            connection_factory.send_to(1, Message::Shutdown());
+
+            assert_eq!(2, connection_factory.total_dropped_messages());
+            assert!(matches!(receiver.recv(), Some(Message::Ping(from, ..)) if from.host() == 3));
+            assert!(matches!(receiver.recv(), Some(Message::PingResponse(4, ..))));
+            assert!(matches!(receiver.recv(), Some(Message::Shutdown())));
+        }
+
+        #[test]
+        fn test_connection_keeps_arrival_order_across_priorities() {
+            let (connection_factory, receiver) = create_simple_connection_factory_with_receiver();
+
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"gossip".to_vec())));
+            connection_factory.send_to(1, Message::PingResponse(3, None, false, Coordinate::new()));
+            connection_factory.send_to(1, Message::Tick());
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(4), Envelope::new("hello", b"gossip".to_vec())));
+
+            assert_eq!(vec![2], receive_request_senders(&receiver, 1));
+            assert!(matches!(receiver.recv(), Some(Message::PingResponse(3, ..))));
+            assert!(matches!(receiver.recv(), Some(Message::Tick())));
+            assert_eq!(vec![4], receive_request_senders(&receiver, 1));
+        }
+
+        #[test]
+        fn test_connection_keeps_network_messages_bounded_while_receiving_control_only() {
+            let (connection_factory, receiver) = create_bounded_connection_factory_with_receiver(DropPolicy::DropNewest);
+
+            for host in 2..5 {
+                connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(host), Envelope::new("hello", b"gossip".to_vec())));
+            }
+            connection_factory.send_to(1, Message::Unpause());
+
+            assert!(matches!(receiver.recv_control(), Some(Message::Unpause())));
+            assert_eq!(1, connection_factory.dropped_messages(1));
+            assert_eq!(vec![2, 3], receive_request_senders(&receiver, 2));
+        }
+
+        #[test]
+        fn test_connection_delays_messages_between_hosts() {
+            let (mut connection_factory, receiver) = create_simple_connection_factory_with_receiver();
+            connection_factory.set_latency(LatencyTopology::new(LatencyDistribution::Constant(Duration::from_millis(100)),
+                                                                LatencyDistribution::Constant(Duration::from_millis(100))));
+            let sent_at = Instant::now();
+
+            connection_factory.send_from(2, 1, Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"delayed".to_vec())));
+            connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(3), Envelope::new("hello", b"direct".to_vec())));
+
+            assert_eq!(vec![3], receive_request_senders(&receiver, 1));
+            assert!(sent_at.elapsed() < Duration::from_millis(100));
+            assert_eq!(vec![2], receive_request_senders(&receiver, 1));
+            assert!(sent_at.elapsed() >= Duration::from_millis(100));
+        }
+
+        fn create_simple_connection_factory_with_receiver() -> (ConnectionFactory, InboxReceiver) {
+            let mut connection_factory = ConnectionFactory::new();
+            let channel = connection_factory.inbox();
+            connection_factory.add_connection(1, channel.0);
+
+            (connection_factory, channel.1)
+        }
+
+        fn create_bounded_connection_factory_with_receiver(policy: DropPolicy) -> (ConnectionFactory, InboxReceiver) {
+            let mut connection_factory = ConnectionFactory::with_capacity(2, policy);
+            let channel = connection_factory.inbox();
+            connection_factory.add_connection(1, channel.0);
+
+            (connection_factory, channel.1)
+        }
+
+        fn receive_request_senders(receiver: &InboxReceiver, number: usize) -> Vec<u16> {
+            (0..number)
+                .map(|_| match receiver.recv() {
+                    Some(Message::Request(from, _)) => from.host(),
+                    _ => panic!("Expected a request message"),
+                })
+                .collect()
+        }
+    }
+
+    mod member_node_tests {
+        use std::iter;
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::Duration;
+        use crate::connection::swim_node::{ConnectionFactory, ConnectionRegistry, DropPolicy};
+        use crate::coordinate::swim_node::Coordinate;
+        use crate::event::swim_node::NodeEvent;
+        use crate::member_node::swim_node::{DefaultMemberNode, MemberNode, MemberNodeDetails, MemberNodeState, MemberNodesRegistry, NodeConfig, PUSH_PULL};
+        use crate::message::swim_node::Message;
+        use crate::rpc::swim_node::Envelope;
+        use crate::run_network;
+
+        #[test]
+        fn test_member_nodes_sending_message() {
+            let connection_factory = ConnectionFactory::new();
+            let connection_ref = Arc::new(Mutex::new(connection_factory));
+            let node1 = DefaultMemberNode::start(1, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
+            let node2 = DefaultMemberNode::start(2, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
+
+            let serialized_details = node1.details().unwrap().serialize();
+            connection_ref.lock().unwrap().send_to(2, Message::Request(serialized_details, Envelope::new("hello", b"hello".to_vec())));
+
+            thread::sleep(Duration::from_secs(1));
+
+            assert_eq!(MemberNodeState::Alive, *node1.details().unwrap().members().get_state_for(2).unwrap());
+            assert_eq!(MemberNodeState::Alive, *node2.details().unwrap().members().get_state_for(1).unwrap());
+        }
+
+        #[test]
+        fn test_member_nodes_when_one_times_out() {
+            let mut router = run_network();
+            router.send(2, 1);
+            let events = router.subscribe();
+
+            router.fail(2);
+
+            let states: Vec<MemberNodeState> = iter::from_fn(|| events.recv_timeout(Duration::from_secs(5)).ok())
+                .filter_map(|e| match e {
+                    (1, NodeEvent::MemberStateChanged(2, state)) => Some(state),
+                    _ => None,
+                })
+                .take_while(|state| *state != MemberNodeState::Failed)
+                .collect();
+
+            assert_eq!(Some(&MemberNodeState::Suspected), states.last());
+            assert_eq!(MemberNodeState::Failed, *router.details(1).unwrap().members().get_state_for(2).unwrap());
+            router.shut_down();
+        }
+
+        #[test]
+        fn test_member_node_publishes_membership_events() {
+            let connection_ref = Arc::new(Mutex::new(ConnectionFactory::new()));
+            let node1 = DefaultMemberNode::start(1, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
+            let events = node1.subscribe();
+
+            connection_ref.lock().unwrap().send_to(1, Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"hello".to_vec())));
+
+            assert_eq!(NodeEvent::MemberStateChanged(2, MemberNodeState::Alive), events.recv_timeout(Duration::from_secs(1)).unwrap());
+        }
+
+        #[test]
+        fn test_member_node_skips_protocol_periods_while_paused() {
+            let mut node = DefaultMemberNode::new(1, NodeConfig::default());
+            node.handle_message(Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"hello".to_vec())));
+
+            assert!(node.handle_message(Message::Pause()).is_empty());
+            assert!(node.is_paused());
+            assert!(node.handle_message(Message::Tick()).is_empty());
+
+            node.handle_message(Message::Unpause());
+
+            assert!(!node.is_paused());
+            assert!(matches!(node.handle_message(Message::Tick()).as_slice(), [(2, Message::Ping(..))]));
+        }
+
+        #[test]
+        fn test_paused_node_leaves_messages_in_bounded_inbox() {
+            let connection_ref = Arc::new(Mutex::new(ConnectionFactory::with_capacity(2, DropPolicy::DropNewest)));
+            let node1 = DefaultMemberNode::start(1, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
+            connection_ref.lock().unwrap().send_to(1, Message::Pause());
+            assert_eq!(Some(true), node1.call(|node| node.is_paused()));
+
+            for host in 2..5 {
+                connection_ref.lock().unwrap().send_to(1, Message::Request(MemberNodeDetails::new(host), Envelope::new("hello", b"hello".to_vec())));
+            }
+            assert_eq!(1, connection_ref.lock().unwrap().dropped_messages(1));
+            assert!(node1.details().unwrap().members().is_empty());
+
+            connection_ref.lock().unwrap().send_to(1, Message::Unpause());
+
+            let members = node1.details().unwrap();
+            assert!(members.members().get_state_for(2).is_some() && members.members().get_state_for(3).is_some());
+            assert_eq!(None, members.members().get_state_for(4));
+        }
+
+        #[test]
+        fn test_member_node_suspects_member_which_missed_ack() {
+            let mut node = DefaultMemberNode::new(1, NodeConfig::default());
+            node.handle_message(Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"hello".to_vec())));
+
+            assert!(matches!(node.handle_message(Message::Tick()).as_slice(), [(2, Message::Ping(..))]));
+            node.handle_message(Message::Tick());
+
+            assert_eq!(Some(&MemberNodeState::Suspected), node.details().members().get_state_for(2));
+        }
+
+        #[test]
+        fn test_member_node_learns_coordinates_from_acks() {
+            let mut node = DefaultMemberNode::new(1, NodeConfig::default());
+            node.handle_message(Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"hello".to_vec())));
+            assert_eq!(None, node.estimate_rtt(1, 2));
+
+            node.handle_message(Message::Tick());
+            thread::sleep(Duration::from_millis(20));
+            node.handle_message(Message::PingResponse(2, None, false, Coordinate::new()));
+
+            assert!(node.coordinate().error < Coordinate::new().error);
+            assert!(node.estimate_rtt(1, 2).is_some());
+            assert_eq!(node.estimate_rtt(1, 2), node.estimate_rtt(2, 1));
+        }
+
+        #[test]
+        fn test_tombstones_reject_stale_resurrection() {
+            let mut members = MemberNodesRegistry::new();
+            assert!(members.add(2, 0));
+            members.set_node_state(2, MemberNodeState::Left);
+
+            assert!(!members.add(2, 0));
+            members.set_node_state(2, MemberNodeState::Alive);
+            assert_eq!(Some(&MemberNodeState::Left), members.get_state_for(2));
+            assert_eq!(None, members.get_random_node());
+
+            assert!(members.add(2, 1));
+            assert_eq!(Some(&MemberNodeState::Alive), members.get_state_for(2));
+            assert_eq!(None, members.tombstoned_since(2));
+        }
+
+        #[test]
+        fn test_tombstones_are_reaped_after_timeout() {
+            let mut members = MemberNodesRegistry::new();
+            members.add(2, 0);
+            members.add(3, 0);
+            members.set_node_state(2, MemberNodeState::Failed);
+
+            assert!(members.reap(Duration::from_secs(60)).is_empty());
+            assert_eq!(vec![2], members.reap(Duration::ZERO));
+            assert_eq!(vec![3], members.hosts());
+            assert!(members.add(2, 0));
+        }
+
+        #[test]
+        fn test_reaped_tombstones_are_not_gossiped_back() {
+            let mut gossip = MemberNodesRegistry::new();
+            gossip.add(2, 0);
+            gossip.set_node_state(2, MemberNodeState::Failed);
+
+            let mut members = MemberNodesRegistry::new();
+            members.add_all(1, &gossip);
+            assert_eq!(None, members.get_state_for(2));
+        }
+
+        #[test]
+        fn test_member_node_refutes_its_failure() {
+            let mut node1 = DefaultMemberNode::new(1, NodeConfig::default());
+            let mut node2 = DefaultMemberNode::new(2, NodeConfig::default());
+            node1.handle_message(Message::Request(node2.serialize_host_details(), Envelope::new("hello", b"hello".to_vec())));
+            for _ in 0..10 {
+                node1.handle_message(Message::Tick());
+            }
+            assert_eq!(Some(&MemberNodeState::Failed), node1.details().members().get_state_for(2));
+
+            let outgoing = node1.handle_message(Message::Ping(node2.serialize_host_details(), None, Coordinate::new()));
+            let notice = outgoing.into_iter().find(|(to, _)| *to == 2).map(|(_, message)| message).unwrap();
+            assert_eq!(Some(&MemberNodeState::Failed), node1.details().members().get_state_for(2));
+
+            let outgoing = node2.handle_message(notice);
+            let rejoin = outgoing.into_iter().find(|(_, m)| matches!(m, Message::Request(..))).map(|(_, m)| m).unwrap();
+            assert_eq!(1, node2.details().incarnation());
+
+            node1.handle_message(rejoin);
+            assert_eq!(Some(&MemberNodeState::Alive), node1.details().members().get_state_for(2));
+        }
+
+        #[test]
+        fn test_member_node_reconnects_to_failed_member() {
+            let config = NodeConfig { reconnect_interval: Duration::ZERO, ..NodeConfig::default() };
+            let mut node = DefaultMemberNode::new(1, config);
+            node.handle_message(Message::Request(MemberNodeDetails::new(2), Envelope::new("hello", b"hello".to_vec())));
+            let mut reconnects = Vec::new();
+            for _ in 0..10 {
+                reconnects.extend(node.handle_message(Message::Tick()).into_iter()
+                    .filter(|(_, m)| matches!(m, Message::Request(_, envelope) if envelope.kind == PUSH_PULL)));
+            }
+
+            assert_eq!(Some(&MemberNodeState::Failed), node.details().members().get_state_for(2));
+            assert!(!reconnects.is_empty());
+            assert!(reconnects.iter().all(|(to, _)| *to == 2));
+        }
+
+        #[test]
+        fn test_member_node_handle_after_shut_down() {
+            let connection_ref = Arc::new(Mutex::new(ConnectionFactory::new()));
+            let node1 = DefaultMemberNode::start(1, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
+
+            node1.change_state(MemberNodeState::Suspected);
+            assert_eq!(Some(MemberNodeState::Suspected), node1.call(|node| *node.details().state()));
+
+            node1.shut_down();
+            thread::sleep(Duration::from_millis(100));
+
+            assert!(node1.call(|node| node.details().host()).is_none());
+        }
+    }
+
+    #[cfg(feature = "async")]
+    mod async_node_tests {
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use tokio::runtime::Builder;
+        use crate::async_node::swim_node::{AsyncMemberNode, AsyncNodeFactory};
+        use crate::connection::swim_node::{ConnectionFactory, ConnectionRegistry, DropPolicy};
+        use crate::member_node::swim_node::{MemberNodeDetails, MemberNodeState, NodeConfig};
+        use crate::message::swim_node::Message;
+        use crate::network_router::{DefaultNodeRequestRouter, FaultInjection, NodeRequestRouter};
+        use crate::rpc::swim_node::Envelope;
+
+        #[test]
+        fn test_async_member_nodes_sending_message() {
+            let runtime = Builder::new_multi_thread().worker_threads(1).enable_time().build().unwrap();
+            let connection_ref = Arc::new(Mutex::new(ConnectionFactory::new()));
+            let node1 = AsyncMemberNode::spawn(1, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref), runtime.handle());
+            let node2 = AsyncMemberNode::spawn(2, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref), runtime.handle());
+
+            let serialized_details = node1.details().unwrap().serialize();
+            connection_ref.lock().unwrap().send_to(2, Message::Request(serialized_details, Envelope::new("hello", b"hello".to_vec())));
+
+            thread::sleep(Duration::from_secs(1));
+
+            assert_eq!(MemberNodeState::Alive, *node1.details().unwrap().members().get_state_for(2).unwrap());
+            assert_eq!(MemberNodeState::Alive, *node2.details().unwrap().members().get_state_for(1).unwrap());
+        }
+
+        #[test]
+        fn test_async_many_nodes_share_small_pool() {
+            let runtime = Builder::new_multi_thread().worker_threads(2).enable_time().build().unwrap();
+            // Every node joins and pings node 1 at once, more than the default inbox holds.
+            let connection_ref = Arc::new(Mutex::new(ConnectionFactory::with_capacity(4096, DropPolicy::PrioritizeAcks)));
+            let nodes: Vec<_> = (1..=1000)
+                .map(|host| AsyncMemberNode::spawn(host, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref), runtime.handle()))
+                .collect();
+
+            for node in nodes.iter().skip(1) {
+                let serialized_details = node.details().unwrap().serialize();
+                connection_ref.lock().unwrap().send_to(1, Message::Request(serialized_details, Envelope::new("hello", b"hello".to_vec())));
+            }
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let knows_everyone = || {
+                let node1 = nodes[0].details().unwrap();
+                (2..=1000).all(|host| node1.members().get_state_for(host).is_some())
+            };
+            while Instant::now() < deadline && !knows_everyone() {
+                thread::sleep(Duration::from_millis(50));
+            }
+            assert!(knows_everyone());
+        }
+
+        #[test]
+        fn test_async_inbox_is_bounded() {
+            let runtime = Builder::new_current_thread().build().unwrap();
+            let mut connection_factory = ConnectionFactory::with_capacity(2, DropPolicy::DropNewest);
+            let (sender, receiver) = connection_factory.inbox();
+            connection_factory.add_connection(1, sender);
+
+            for host in 2..5 {
+                connection_factory.send_to(1, Message::Request(MemberNodeDetails::new(host), Envelope::new("hello", b"hello".to_vec())));
+            }
+
+            assert_eq!(1, connection_factory.dropped_messages(1));
+            let senders: Vec<u16> = (0..2)
+                .map(|_| match runtime.block_on(receiver.recv_async()) {
+                    Some(Message::Request(from, _)) => from.host(),
+                    _ => panic!("Expected a request message"),
+                })
+                .collect();
+            assert_eq!(vec![2, 3], senders);
+        }
+
+        #[test]
+        fn test_router_drives_async_nodes_through_partitions() {
+            let runtime = Builder::new_multi_thread().worker_threads(2).enable_time().build().unwrap();
+            let config = NodeConfig { ping_interval: Duration::from_millis(50), ..NodeConfig::default() };
+            let node_factory = AsyncNodeFactory::new(config, runtime.handle().clone());
+            let mut router = DefaultNodeRequestRouter::new(Box::new(node_factory), Arc::new(Mutex::new(ConnectionFactory::new())));
+            router.start();
+            router.send(2, 1);
+
+            let joined = Instant::now() + Duration::from_secs(3);
+            while Instant::now() < joined && router.node(2).unwrap().details().unwrap().members().get_state_for(1).is_none() {
+                thread::sleep(Duration::from_millis(20));
+            }
+            assert_eq!(MemberNodeState::Alive, *router.node(1).unwrap().details().unwrap().members().get_state_for(2).unwrap());
+
+            router.partition(vec![vec![1], vec![2]]);
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline && *router.node(1).unwrap().details().unwrap().members().get_state_for(2).unwrap() == MemberNodeState::Alive {
+                thread::sleep(Duration::from_millis(20));
+            }
+            assert_ne!(MemberNodeState::Alive, *router.node(1).unwrap().details().unwrap().members().get_state_for(2).unwrap());
+            router.shut_down();
+        }
+    }
+
+    mod metrics_tests {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::Duration;
+        use crate::connection::swim_node::{ConnectionFactory, ConnectionRegistry};
+        use crate::coordinate::swim_node::Coordinate;
+        use crate::member_node::swim_node::{DefaultMemberNode, MemberNodeDetails};
+        use crate::message::swim_node::Message;
+        use crate::rpc::swim_node::Envelope;
+        use crate::metrics::swim_node::{export_prometheus, metrics, serve};
+
+        #[test]
+        fn test_metrics_count_probes_and_acks() {
+            let probes_sent = metrics().probes_sent.get();
+            let acks_received = metrics().acks_received.get();
+            let messages_sent = metrics().messages_sent.get();
+
+            let connection_ref = Arc::new(Mutex::new(ConnectionFactory::new()));
+            let node1 = DefaultMemberNode::start(1, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
+            let _node2 = DefaultMemberNode::start(2, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
+            connection_ref.lock().unwrap().send_to(2, Message::Request(node1.details().unwrap(), Envelope::new("hello", b"hello".to_vec())));
+
+            thread::sleep(Duration::from_millis(1500));
+
+            assert!(metrics().probes_sent.get() >= probes_sent + 2);
+            assert!(metrics().acks_received.get() >= acks_received + 2);
+            assert!(metrics().messages_sent.get() >= messages_sent + 6);
+        }
+
+        #[test]
+        fn test_metrics_prometheus_text_format() {
+            let text = export_prometheus();
+
+            assert!(text.contains("# TYPE swim_probes_sent_total counter"));
+            assert!(text.contains("# TYPE swim_inbox_depth gauge"));
+            assert!(text.contains("swim_message_size_bytes_bucket{le=\"+Inf\"}"));
+            assert!(text.contains("swim_message_size_bytes_count"));
+        }
+
+        #[test]
+        fn test_message_encoded_len_grows_with_payload() {
+            let short = Message::Request(MemberNodeDetails::new(1), Envelope::new("hello", b"hello".to_vec())).encoded_len();
+            let long = Message::Request(MemberNodeDetails::new(1), Envelope::new("hello", b"hello world".to_vec())).encoded_len();
+
+            assert_eq!(short + 6, long);
+            assert!(Message::Ping(MemberNodeDetails::new(1), Some(MemberNodeDetails::new(2)), Coordinate::new()).encoded_len()
+                > Message::Ping(MemberNodeDetails::new(1), None, Coordinate::new()).encoded_len());
+            assert_eq!(0, Message::Tick().encoded_len());
+        }
+
+        #[test]
+        fn test_metrics_served_over_http() {
+            let address = serve("127.0.0.1:0").unwrap();
+
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+            assert!(response.contains("swim_messages_sent_total"));
+
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+
+            assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+        }
+    }
+
+    mod convergence_tests {
+        use std::time::Duration;
+        use crate::convergence::swim_node::{ClusterEvent, ConvergenceConfig, measure_convergence};
+        use crate::member_node::swim_node::NodeConfig;
+
+        fn config() -> ConvergenceConfig {
+            ConvergenceConfig {
+                cluster_size: 5,
+                node_config: NodeConfig { ping_interval: Duration::from_millis(50), ..NodeConfig::default() },
+                timeout: Duration::from_secs(10),
+            }
+        }
+
+        #[test]
+        fn test_convergence_of_join() {
+            let report = measure_convergence(&config(), ClusterEvent::Join);
+
+            assert!(report.is_converged(), "{}", report);
+            assert_eq!(5, report.time_to_know.len());
+            assert!(report.percentile(50.0).unwrap() <= report.percentile(100.0).unwrap());
+            assert!(report.messages > 0);
+        }
+
+        #[test]
+        fn test_convergence_of_failure() {
+            let report = measure_convergence(&config(), ClusterEvent::Failure);
+
+            assert!(report.is_converged(), "{}", report);
+            assert_eq!(4, report.time_to_know.len());
+            assert!(report.percentile(100.0).unwrap() >= Duration::from_millis(50));
+        }
+    }
+
+    mod scenario_tests {
+        use std::time::Duration;
+        use crate::member_node::swim_node::MemberNodeState;
+        use crate::run_network_with_config;
+        use crate::scenario::swim_node::{Assertion, Command, Scenario};
+
+        fn run(text: &str) -> Result<(), String> {
+            let scenario = Scenario::parse(text).map_err(|err| err.to_string())?;
+            let mut router = run_network_with_config(scenario.node_config().clone());
+            let result = scenario.run(router.as_mut()).map_err(|err| err.to_string());
+            router.shut_down();
+            result
+        }
+
+        #[test]
+        fn test_scenario_parse_commands() {
+            let scenario = Scenario::parse("# comment\nset ping-interval 200ms\n\nsend 2 1\npartition 1, 2 | 3\nexpect within 2s 1 sees 2 suspected\n").unwrap();
+
+            assert_eq!(Duration::from_millis(200), scenario.node_config().ping_interval);
+            assert_eq!(vec![
+                &Command::Send(2, 1),
+                &Command::Partition(vec![vec![1, 2], vec![3]]),
+                &Command::Expect(Assertion::Sees(1, 2, MemberNodeState::Suspected), Some(Duration::from_secs(2))),
+            ], scenario.commands());
+        }
+
+        #[test]
+        fn test_scenario_parse_error_reports_line() {
+            let error = Scenario::parse("add 1\nexplode 2\n").err().unwrap();
+
+            assert_eq!(2, error.line);
+            assert_eq!("line 2: unknown command 'explode 2'", error.to_string());
+        }
+
+        #[test]
+        fn test_scenario_failed_assertion() {
+            let result = run("set ping-interval 100ms\nsend 2 1\nexpect within 200ms 1 sees 2 failed\n");
+
+            assert_eq!(Err(String::from("line 3: node 1 sees node 2 as Alive, expected Failed")), result);
+        }
+
+        #[test]
+        fn test_checked_in_failure_detection_scenario() {
+            assert_eq!(Ok(()), run(include_str!("../../scenarios/failure_detection.swim")));
+        }
+
+        #[test]
+        fn test_checked_in_crash_restart_scenario() {
+            assert_eq!(Ok(()), run(include_str!("../../scenarios/crash_restart.swim")));
+        }
+
+        #[test]
+        fn test_checked_in_partition_scenario() {
+            assert_eq!(Ok(()), run(include_str!("../../scenarios/partition.swim")));
+        }
+
+        #[test]
+        fn test_checked_in_partition_heal_scenario() {
+            assert_eq!(Ok(()), run(include_str!("../../scenarios/partition_heal.swim")));
+        }
+
+        #[test]
+        fn test_checked_in_leader_election_scenario() {
+            assert_eq!(Ok(()), run(include_str!("../../scenarios/leader_election.swim")));
+        }
+    }
+
+    mod repl_tests {
+        use std::io::Cursor;
+        use std::time::Duration;
+        use crate::member_node::swim_node::NodeConfig;
+        use crate::repl::swim_node::run_repl;
+        use crate::run_network_with_config;
+
+        #[test]
+        fn test_repl_runs_commands() {
+            let mut router = run_network_with_config(NodeConfig { ping_interval: Duration::from_secs(60), ..NodeConfig::default() });
+            let input = Cursor::new("add 2\nsend 2 1\nwait 100ms\nkill 2\ntick\nwait 100ms\nmembers 1\nrevive 2\nfly 1\nquit\nmembers 1\n");
+            let mut output = Vec::new();
+
+            run_repl(input, &mut output, router.as_mut()).unwrap();
+            router.shut_down();
+
+            let output = String::from_utf8(output).unwrap();
+            assert!(output.contains("node 1 is Alive, knows 1 members\n  2 Suspected"), "{}", output);
+            assert!(output.contains("error: unknown command 'fly 1'"), "{}", output);
+            assert_eq!(1, output.matches("knows 1 members").count());
+        }
+    }
+
+    mod dashboard_tests {
+        use std::time::Duration;
+        use crate::dashboard::swim_node::Dashboard;
+        use crate::event::swim_node::NodeEvent;
+        use crate::member_node::swim_node::{MemberNodeState, NodeConfig};
+        use crate::run_network_with_config;
+
+        #[test]
+        fn test_dashboard_renders_membership_matrix() {
+            let mut dashboard = Dashboard::new();
+            dashboard.apply(1, &NodeEvent::MemberStateChanged(2, MemberNodeState::Alive));
+            dashboard.apply(1, &NodeEvent::MemberStateChanged(3, MemberNodeState::Suspected));
+            dashboard.apply(2, &NodeEvent::MemberStateChanged(3, MemberNodeState::Failed));
+            dashboard.apply(2, &NodeEvent::MemberStateChanged(1, MemberNodeState::Alive));
+            dashboard.apply(2, &NodeEvent::MemberRemoved(3));
+
+            let text = dashboard.render(false);
+
+            assert!(text.starts_with("SWIM cluster: 2 nodes"), "{}", text);
+            assert!(text.contains("          1  2  3\nnode 1    -  A  S\nnode 2    A  -  .\n"), "{}", text);
+            assert!(text.contains("] node 2 sees 3 Failed\n"), "{}", text);
+            assert!(text.ends_with("] node 2 removed 3\n"), "{}", text);
+        }
+
+        #[test]
+        fn test_router_event_feed_includes_nodes_added_later() {
+            let mut router = run_network_with_config(NodeConfig { ping_interval: Duration::from_secs(60), ..NodeConfig::default() });
+            let events = router.subscribe();
+
+            router.send(2, 1);
+
+            let mut received = Vec::new();
+            while received.len() < 2 {
+                received.push(events.recv_timeout(Duration::from_secs(1)).unwrap());
+            }
+            router.shut_down();
+
+            received.sort_by_key(|(host, _)| *host);
+            assert_eq!(vec![
+                (1, NodeEvent::MemberStateChanged(2, MemberNodeState::Alive)),
+                (2, NodeEvent::MemberStateChanged(1, MemberNodeState::Alive)),
+            ], received);
+        }
+    }
+
+    mod snapshot_tests {
+        use std::fs;
+        use std::path::PathBuf;
+        use std::process;
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::member_node::swim_node::{MemberNodeState, NodeConfig};
+        use crate::run_network_with_config;
+        use crate::snapshot::swim_node::{Snapshot, SnapshotError};
+
+        #[test]
+        fn test_snapshot_save_and_load() {
+            let dir = snapshot_dir("round_trip");
+            let snapshot = Snapshot { incarnation: 3, members: vec![(2, MemberNodeState::Alive), (5, MemberNodeState::Suspected)] };
+
+            snapshot.save(&Snapshot::path(&dir, 1)).unwrap();
+
+            assert_eq!(snapshot, Snapshot::load(&Snapshot::path(&dir, 1)).unwrap());
+            assert_eq!(vec![2], snapshot.alive_members());
+            let _ = fs::remove_dir_all(dir);
+        }
+
+        #[test]
+        fn test_snapshot_detects_corruption() {
+            let dir = snapshot_dir("corruption");
+            let path = Snapshot::path(&dir, 1);
+            Snapshot { incarnation: 1, members: vec![(2, MemberNodeState::Alive)] }.save(&path).unwrap();
+            let text = fs::read_to_string(&path).unwrap().replace("member 2", "member 3");
+
+            assert!(matches!(Snapshot::parse(&text), Err(SnapshotError::Corrupted(_))));
+            assert!(matches!(Snapshot::parse("swim-snapshot 1\nincarn"), Err(SnapshotError::Corrupted(_))));
+            let _ = fs::remove_dir_all(dir);
+        }
+
+        #[test]
+        fn test_restarted_node_rejoins_members_from_snapshot() {
+            let dir = snapshot_dir("rejoin");
+            let config = NodeConfig { ping_interval: Duration::from_millis(50), snapshot_dir: Some(dir.clone()), ..NodeConfig::default() };
+            let mut router = run_network_with_config(config);
+            router.send(2, 1);
+            router.send(3, 1);
+            wait_until(|| Snapshot::load(&Snapshot::path(&dir, 2)).is_ok_and(|s| s.alive_members() == vec![1, 3]));
+
+            router.crash(2);
+            router.restart(2);
+
+            wait_until(|| router.details(2).is_some_and(|d| d.members().len() == 2));
+            assert_eq!(1, router.details(2).unwrap().incarnation());
+            router.shut_down();
+            let _ = fs::remove_dir_all(dir);
+        }
+
+        #[test]
+        fn test_node_with_corrupted_snapshot_joins_seeds() {
+            let dir = snapshot_dir("seeds");
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(Snapshot::path(&dir, 2), "swim-snapshot 1\nincarnation 7\nmember 3 Ali").unwrap();
+            let config = NodeConfig { snapshot_dir: Some(dir.clone()), seeds: vec![1], ..NodeConfig::default() };
+            let mut router = run_network_with_config(config);
+
+            router.add(2);
+
+            wait_until(|| router.details(1).is_some_and(|d| d.members().get_state_for(2) == Some(&MemberNodeState::Alive)));
+            assert_eq!(0, router.details(2).unwrap().incarnation());
+            router.shut_down();
+            let _ = fs::remove_dir_all(dir);
+        }
+
+        fn snapshot_dir(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!("swim-snapshot-{}-{}", process::id(), name))
+        }
+
+        fn wait_until<F: Fn() -> bool>(condition: F) {
+            let started = Instant::now();
+            while !condition() {
+                assert!(started.elapsed() < Duration::from_secs(5), "condition not met within 5s");
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+    }
+
+    mod user_event_tests {
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::connection::swim_node::ConnectionFactory;
+        use crate::event::swim_node::NodeEvent;
+        use crate::member_node::swim_node::{MemberNode, NodeConfig};
+        use crate::network_router::{ClusterMessaging, DefaultNodeFactory, DefaultNodeRequestRouter, NodeRequestRouter};
+        use crate::user_event::swim_node::{UserEvent, UserEvents};
+
+        #[test]
+        fn test_user_events_are_delivered_once() {
+            let mut events = UserEvents::new();
+            let event = UserEvent { name: String::from("deploy"), payload: vec![1], ltime: 4, origin: 2 };
+
+            assert_eq!(Some(event.clone()), events.receive(event.clone()));
+            assert_eq!(None, events.receive(event));
+            assert_eq!(5, events.clock());
+            assert_eq!(6, events.broadcast(1, "restart", Vec::new()).ltime);
+        }
+
+        #[test]
+        fn test_user_events_coalesce_by_name() {
+            let mut events = UserEvents::new();
+            let older = UserEvent { name: String::from("config"), payload: vec![1], ltime: 1, origin: 2 };
+            let newer = UserEvent { name: String::from("config"), payload: vec![2], ltime: 2, origin: 3 };
+
+            assert!(events.receive(newer.clone()).is_some());
+            assert_eq!(None, events.receive(older));
+            assert_eq!(vec![newer], events.piggyback());
+        }
+
+        #[test]
+        fn test_user_events_stop_being_gossiped() {
+            let mut events = UserEvents::new();
+            events.broadcast(1, "deploy", Vec::new());
+
+            for _ in 0..6 {
+                events.expire(3);
+            }
+            assert_eq!(1, events.piggyback().len());
+
+            events.expire(3);
+            assert!(events.piggyback().is_empty());
+        }
+
+        #[test]
+        fn test_user_event_reaches_every_node() {
+            let node_factory = DefaultNodeFactory::with_config(NodeConfig { ping_interval: Duration::from_millis(50), ..NodeConfig::default() });
+            let mut router = DefaultNodeRequestRouter::new(Box::new(node_factory), Arc::new(Mutex::new(ConnectionFactory::new())));
+            router.start();
+            router.send(2, 1);
+            router.send(3, 1);
+            let receivers: Vec<_> = [1, 2].iter().map(|h| router.node(*h).unwrap().subscribe()).collect();
+
+            router.broadcast_event(3, "deploy", b"v2".to_vec());
+
+            let deadline = Instant::now() + Duration::from_secs(3);
+            let mut delivered: Vec<Vec<UserEvent>> = vec![Vec::new(); receivers.len()];
+            while Instant::now() < deadline && delivered.iter().any(|d| d.is_empty()) {
+                for (receiver, events) in receivers.iter().zip(delivered.iter_mut()) {
+                    if let Ok(NodeEvent::UserEvent(e)) = receiver.recv_timeout(Duration::from_millis(10)) {
+                        events.push(e);
+                    }
+                }
+            }
+            thread::sleep(Duration::from_millis(300));
+            for (receiver, mut delivered) in receivers.into_iter().zip(delivered) {
+                delivered.extend(receiver.try_iter().filter_map(|e| match e {
+                    NodeEvent::UserEvent(e) => Some(e),
+                    _ => None,
+                }));
+                assert_eq!(1, delivered.len());
+                assert_eq!(("deploy", &b"v2"[..], 3), (delivered[0].name.as_str(), delivered[0].payload.as_slice(), delivered[0].origin));
+            }
+            router.shut_down();
+        }
+    }
+
+    mod query_tests {
+        use std::collections::BTreeMap;
+        use std::sync::mpsc;
+        use std::sync::mpsc::{Receiver, TryRecvError};
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::member_node::swim_node::{DefaultMemberNode, NodeConfig};
+        use crate::network_router::NodeRequestRouter;
+        use crate::query::swim_node::{Query, QueryEvent, QueryParams};
+        use crate::run_network_with_config;
+
+        #[test]
+        fn test_query_filter_matches_tags() {
+            let query = Query { id: 1, name: String::from("load"), payload: Vec::new(), filter: tags(&[("role", "db")]) };
+
+            assert!(query.matches(&tags(&[("role", "db"), ("zone", "a")])));
+            assert!(!query.matches(&tags(&[("role", "web")])));
+            assert!(!query.matches(&BTreeMap::new()));
+        }
+
+        #[test]
+        fn test_query_collects_acks_and_responses() {
+            let mut router = run_network_with_config(NodeConfig { ping_interval: Duration::from_secs(60), ..NodeConfig::default() });
+            router.send(2, 1);
+            router.send(3, 1);
+            for host in [2u16, 3] {
+                router.handle_query(host, "load", Box::new(move |payload| [payload, &host.to_be_bytes()[..]].concat()));
+            }
+            router.set_tags(3, tags(&[("role", "db")]));
+            wait_until_known(&*router, 1, 2);
+
+            let all = collect(router.query(1, "load", vec![7], QueryParams { timeout: Duration::from_millis(300), ..QueryParams::default() }));
+            let filtered = collect(router.query(1, "load", vec![7], QueryParams { filter: tags(&[("role", "db")]), timeout: Duration::from_millis(300) }));
+            router.shut_down();
+
+            assert_eq!(vec![
+                QueryEvent::Ack(1),
+                QueryEvent::Ack(2),
+                QueryEvent::Ack(3),
+                QueryEvent::Response(2, vec![7, 0, 2]),
+                QueryEvent::Response(3, vec![7, 0, 3]),
+            ], all);
+            assert_eq!(vec![QueryEvent::Ack(3), QueryEvent::Response(3, vec![7, 0, 3])], filtered);
+        }
+
+        #[test]
+        fn test_query_expires_at_its_timeout() {
+            let mut node = DefaultMemberNode::new(1, NodeConfig::default());
+            let (sender, results) = mpsc::channel();
+            node.query("load", Vec::new(), QueryParams { timeout: Duration::from_millis(20), ..QueryParams::default() }, sender);
+            let deadline = node.next_deadline().unwrap();
+
+            node.expire_pending();
+            assert_eq!(Err(TryRecvError::Empty), results.try_recv());
+
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            node.expire_pending();
+            assert_eq!(None, node.next_deadline());
+            assert_eq!(Err(TryRecvError::Disconnected), results.try_recv());
+        }
+
+        #[test]
+        fn test_query_from_unknown_node_ends_immediately() {
+            let mut router = run_network_with_config(NodeConfig::default());
+
+            assert!(router.query(9, "load", Vec::new(), QueryParams::default()).recv().is_err());
+            router.shut_down();
+        }
+
+        /// Reads the stream until the query times out, ordering acks before responses.
+        fn collect(results: Receiver<QueryEvent>) -> Vec<QueryEvent> {
+            let started = Instant::now();
+            let mut events: Vec<QueryEvent> = results.iter().collect();
+            assert!(started.elapsed() < Duration::from_secs(2));
+            events.sort_by_key(|e| match e {
+                QueryEvent::Ack(host) => (0, *host),
+                QueryEvent::Response(host, _) => (1, *host),
+            });
+            events
+        }
+
+        fn wait_until_known(router: &dyn NodeRequestRouter, host: u16, members: usize) {
+            let started = Instant::now();
+            while router.details(host).unwrap().members().len() < members {
+                assert!(started.elapsed() < Duration::from_secs(3));
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+
+        fn tags(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+            pairs.iter().map(|(k, v)| (String::from(*k), String::from(*v))).collect()
+        }
+    }
+
+    mod rpc_tests {
+        use std::sync::mpsc;
+        use std::sync::mpsc::TryRecvError;
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::member_node::swim_node::{DefaultMemberNode, NodeConfig};
+        use crate::rpc::swim_node::{Codec, CodecError, request, RpcError, typed_handler};
+        use crate::run_network_with_config;
+
+        #[test]
+        fn test_codecs_round_trip() {
+            assert_eq!(Ok(String::from("héllo")), String::decode(&String::from("héllo").encode()));
+            assert_eq!(Ok(42u64), u64::decode(&42u64.encode()));
+            assert_eq!(Ok(vec![1u8, 2]), Vec::<u8>::decode(&vec![1u8, 2].encode()));
+            assert!(matches!(u64::decode(&[1, 2]), Err(CodecError(_))));
+        }
+
+        #[test]
+        fn test_typed_request_between_nodes() {
+            let mut router = run_network_with_config(NodeConfig { ping_interval: Duration::from_secs(60), ..NodeConfig::default() });
+            router.add(2);
+            router.handle_requests(2, "sum", typed_handler(|from, numbers: Vec<u8>| numbers.iter().map(|n| *n as u64).sum::<u64>() + from as u64));
+            router.handle_requests(2, "greet", typed_handler(|from, name: String| format!("hello {} from {}", name, from)));
+
+            let sum: Result<u64, RpcError> = request(&mut *router, 1, 2, "sum", &vec![1u8, 2, 3], Duration::from_secs(1));
+            let greeting: Result<String, RpcError> = request(&mut *router, 1, 2, "greet", &String::from("bob"), Duration::from_secs(1));
+            let unhandled = router.request(1, 2, "unknown", vec![1], Duration::from_secs(1)).recv_timeout(Duration::from_secs(1));
+
+            assert_eq!(Ok(7), sum);
+            assert_eq!(Ok(String::from("hello bob from 1")), greeting);
+            assert_eq!(Ok(Vec::new()), unhandled);
+            router.shut_down();
+        }
+
+        #[test]
+        fn test_request_expires_at_its_timeout() {
+            let mut node = DefaultMemberNode::new(1, NodeConfig::default());
+            let (sender, responses) = mpsc::channel();
+            node.request(2, "greet", Vec::new(), Duration::from_millis(20), sender);
+            let deadline = node.next_deadline().unwrap();
+
+            node.expire_pending();
+            assert_eq!(Err(TryRecvError::Empty), responses.try_recv());
+
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            node.expire_pending();
+            assert_eq!(None, node.next_deadline());
+            assert_eq!(Err(TryRecvError::Disconnected), responses.try_recv());
+        }
+
+        #[test]
+        fn test_request_to_crashed_node_times_out() {
+            let mut router = run_network_with_config(NodeConfig { ping_interval: Duration::from_secs(60), ..NodeConfig::default() });
+            router.add(2);
+            router.crash(2);
+            let started = Instant::now();
+
+            let response: Result<String, RpcError> = request(&mut *router, 1, 2, "greet", &String::from("bob"), Duration::from_millis(200));
+
+            assert_eq!(Err(RpcError::Timeout), response);
+            assert!(started.elapsed() < Duration::from_secs(1));
+            router.shut_down();
+        }
+    }
+
+    mod ring_tests {
+        use std::collections::{BTreeMap, HashMap};
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::connection::swim_node::ConnectionFactory;
+        use crate::event::swim_node::NodeEvent;
+        use crate::member_node::swim_node::{MemberNodeState, NodeConfig};
+        use crate::network_router::{DefaultNodeFactory, DefaultNodeRequestRouter, FaultInjection, NodeRequestRouter};
+        use crate::ring::swim_node::{HashRing, WEIGHT_TAG};
+        use crate::run_network_with_config;
+
+        const KEYS: usize = 10000;
+
+        #[test]
+        fn test_ring_spreads_keys_evenly() {
+            let ring = ring_of(&[1, 2, 3, 4, 5]);
+
+            let counts = ownership(&ring);
+
+            let mean = KEYS / 5;
+            for (host, count) in counts {
+                assert!(count > mean * 7 / 10 && count < mean * 13 / 10, "node {} owns {} of {} keys", host, count, KEYS);
+            }
+        }
+
+        #[test]
+        fn test_ring_respects_weights() {
+            let mut ring = ring_of(&[1, 2, 3]);
+            ring.apply(&NodeEvent::MemberTagsChanged(3, BTreeMap::from([(String::from(WEIGHT_TAG), String::from("3"))])));
+
+            let counts = ownership(&ring);
+
+            assert!(counts[&3] > counts[&1] * 2 && counts[&3] > counts[&2] * 2, "{:?}", counts);
+        }
+
+        #[test]
+        fn test_ring_moves_only_keys_of_changed_member() {
+            let mut ring = ring_of(&[1, 2, 3, 4]);
+            let before = owners_by_key(&ring);
+
+            ring.apply(&NodeEvent::MemberStateChanged(5, MemberNodeState::Alive));
+            let joined = owners_by_key(&ring);
+            let moved: Vec<usize> = (0..KEYS).filter(|k| before[*k] != joined[*k]).collect();
+            assert!(moved.iter().all(|k| joined[*k] == 5));
+            assert!(moved.len() < KEYS * 3 / 10, "{} keys moved", moved.len());
+
+            ring.apply(&NodeEvent::MemberStateChanged(2, MemberNodeState::Failed));
+            let failed = owners_by_key(&ring);
+            assert!((0..KEYS).filter(|k| joined[*k] != failed[*k]).all(|k| joined[k] == 2));
+            assert_eq!(vec![1, 3, 4, 5], ring.members());
+        }
+
+        #[test]
+        fn test_ring_replicas_are_distinct_members() {
+            let mut ring = ring_of(&[1, 2, 3]);
+            ring.apply(&NodeEvent::MemberStateChanged(2, MemberNodeState::Suspected));
+
+            let owners = ring.owners(b"key", 5);
+
+            assert_eq!(3, owners.len());
+            let mut sorted = owners.clone();
+            sorted.sort_unstable();
+            assert_eq!(vec![1, 2, 3], sorted);
+            assert_eq!(Some(owners[0]), ring.owner(b"key"));
+            assert!(HashRing::new(10).owners(b"key", 3).is_empty());
+        }
+
+        #[test]
+        fn test_ring_follows_node_membership() {
+            let node_factory = DefaultNodeFactory::with_config(NodeConfig { ping_interval: Duration::from_millis(50), ..NodeConfig::default() });
+            let mut router = DefaultNodeRequestRouter::new(Box::new(node_factory), Arc::new(Mutex::new(ConnectionFactory::new())));
+            router.start();
+            let ring = HashRing::follow(router.node(1).unwrap(), 50).unwrap();
+
+            router.send(2, 1);
+            router.send(3, 1);
+            wait_until(|| ring.read().unwrap().members() == vec![1, 2, 3]);
+
+            router.crash(3);
+            wait_until(|| ring.read().unwrap().members() == vec![1, 2]);
+            router.shut_down();
+        }
+
+        #[test]
+        fn test_member_tags_are_gossiped() {
+            let mut router = run_network_with_config(NodeConfig { ping_interval: Duration::from_millis(50), ..NodeConfig::default() });
+            router.send(2, 1);
+            router.send(3, 1);
+            router.set_tags(3, BTreeMap::from([(String::from(WEIGHT_TAG), String::from("2"))]));
+            router.send(3, 1);
+
+            wait_until(|| router.details(2).unwrap().members().get_tags_for(3).is_some_and(|t| t[WEIGHT_TAG] == "2"));
+            router.shut_down();
+        }
+
+        fn ring_of(hosts: &[u16]) -> HashRing {
+            let mut ring = HashRing::new(100);
+            for host in hosts {
+                ring.apply(&NodeEvent::MemberStateChanged(*host, MemberNodeState::Alive));
+            }
+            ring
+        }
+
+        fn owners_by_key(ring: &HashRing) -> Vec<u16> {
+            (0..KEYS).map(|k| ring.owner(format!("key-{}", k).as_bytes()).unwrap()).collect()
+        }
+
+        fn ownership(ring: &HashRing) -> HashMap<u16, usize> {
+            let mut counts = HashMap::new();
+            for owner in owners_by_key(ring) {
+                *counts.entry(owner).or_insert(0) += 1;
+            }
+            counts
+        }
+
+        fn wait_until<F: Fn() -> bool>(condition: F) {
+            let started = Instant::now();
+            while !condition() {
+                assert!(started.elapsed() < Duration::from_secs(5), "condition not met within 5s");
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+    }
+
+    mod rendezvous_tests {
+        use std::collections::{BTreeMap, HashSet};
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::member_node::swim_node::NodeConfig;
+        use crate::rendezvous::swim_node::{Rendezvous, ZONE_TAG};
+        use crate::run_network_with_config;
+
+        const KEYS: usize = 5000;
+
+        #[test]
+        fn test_rendezvous_replicas_span_zones() {
+            let rendezvous = zoned(&[(1, "a"), (2, "a"), (3, "b"), (4, "b"), (5, "c")]);
+
+            for k in 0..KEYS {
+                let key = format!("key-{}", k);
+                let replicas = rendezvous.replicas(key.as_bytes(), 3);
+                let zones: HashSet<&str> = replicas.iter().map(|h| rendezvous.zone_of(*h).unwrap()).collect();
+                assert_eq!(3, zones.len(), "replicas {:?} of {}", replicas, key);
+                assert_eq!(rendezvous.owner(key.as_bytes()), Some(replicas[0]));
+            }
+        }
+
+        #[test]
+        fn test_rendezvous_shares_zones_when_there_are_too_few() {
+            let rendezvous = zoned(&[(1, "a"), (2, "a"), (3, "b")]);
+
+            let replicas = rendezvous.replicas(b"key", 5);
+
+            assert_eq!(3, replicas.len());
+            assert_ne!(rendezvous.zone_of(replicas[0]), rendezvous.zone_of(replicas[1]));
+        }
+
+        #[test]
+        fn test_rendezvous_removal_moves_only_owned_keys() {
+            let before = Rendezvous::new((1..=5).map(|h| (h, None)));
+            let after = Rendezvous::new((1..=5).filter(|h| *h != 3).map(|h| (h, None)));
+
+            let mut owned = [0; 6];
+            for k in 0..KEYS {
+                let key = format!("key-{}", k);
+                let (old, new) = (before.owner(key.as_bytes()).unwrap(), after.owner(key.as_bytes()).unwrap());
+                owned[old as usize] += 1;
+                assert!(old == new || old == 3, "{} moved from {} to {}", key, old, new);
+            }
+            assert!(owned[1..].iter().all(|c| *c > KEYS / 5 * 7 / 10), "{:?}", owned);
+        }
+
+        #[test]
+        fn test_rendezvous_agrees_across_nodes() {
+            let mut router = run_network_with_config(NodeConfig { ping_interval: Duration::from_millis(50), ..NodeConfig::default() });
+            for (host, zone) in [(2u16, "a"), (3, "b"), (4, "b")] {
+                router.send(host, 1);
+                router.set_tags(host, BTreeMap::from([(String::from(ZONE_TAG), String::from(zone))]));
+            }
+            router.set_tags(1, BTreeMap::from([(String::from(ZONE_TAG), String::from("a"))]));
+
+            let started = Instant::now();
+            loop {
+                let views: Vec<Rendezvous> = (1..=4).map(|h| Rendezvous::from_details(&router.details(h).unwrap())).collect();
+                let complete = views.iter().all(|v| v.len() == 4 && (1..=4).all(|h| v.zone_of(h).is_some()));
+                if complete {
+                    for k in 0..100 {
+                        let key = format!("key-{}", k);
+                        let replicas = views[0].replicas(key.as_bytes(), 2);
+                        assert!(views.iter().all(|v| v.replicas(key.as_bytes(), 2) == replicas));
+                    }
+                    break;
+                }
+                assert!(started.elapsed() < Duration::from_secs(5), "views didn't converge");
+                thread::sleep(Duration::from_millis(20));
+            }
+            router.shut_down();
+        }
+
+        fn zoned(members: &[(u16, &str)]) -> Rendezvous {
+            Rendezvous::new(members.iter().map(|(h, z)| (*h, Some(String::from(*z)))))
+        }
+    }
+
+    mod election_tests {
+        use std::time::{Duration, Instant};
+        use crate::election::swim_node::{Election, ElectionConfig, Leadership};
+        use crate::member_node::swim_node::{MemberNodeState, MemberNodesRegistry};
+
+        const STABILITY: Duration = Duration::from_millis(100);
+        const LEASE: Duration = Duration::from_millis(500);
+
+        fn election(host: u16, now: Instant) -> Election {
+            Election::new(host, ElectionConfig { stability: STABILITY, lease: LEASE }, now)
+        }
+
+        fn registry(members: &[(u16, MemberNodeState)]) -> MemberNodesRegistry {
+            let mut registry = MemberNodesRegistry::new();
+            for (host, state) in members {
+                registry.add(*host, 0);
+                registry.set_node_state(*host, *state);
+            }
+            registry
+        }
+
+        fn elected(host: u16, members: &MemberNodesRegistry, start: Instant) -> Election {
+            let mut election = election(host, start);
+            election.update(members, start);
+            election.update(members, start + LEASE);
+            election
+        }
+
+        #[test]
+        fn test_election_waits_for_stable_members() {
+            let start = Instant::now();
+            let members = registry(&[(1, MemberNodeState::Alive), (3, MemberNodeState::Alive)]);
+            let mut election = election(2, start);
+
+            assert!(!election.update(&members, start));
+            assert_eq!(None, election.leadership().leader);
+            assert!(election.update(&members, start + LEASE));
+            assert_eq!(Some(1), election.leadership().leader);
+            assert!(!election.is_leader());
+        }
+
+        #[test]
+        fn test_election_keeps_suspected_leader() {
+            let start = Instant::now();
+            let mut members = registry(&[(1, MemberNodeState::Alive), (3, MemberNodeState::Alive)]);
+            let mut election = elected(2, &members, start);
+
+            members.set_node_state(1, MemberNodeState::Suspected);
+
+            assert!(!election.update(&members, start + LEASE * 10));
+            assert_eq!(Some(1), election.leadership().leader);
+        }
+
+        #[test]
+        fn test_election_replaces_failed_leader_after_lease() {
+            let start = Instant::now();
+            let mut members = registry(&[(1, MemberNodeState::Alive), (3, MemberNodeState::Alive)]);
+            let mut election = elected(2, &members, start);
+            let failed_at = start + LEASE * 2;
+
+            members.set_node_state(1, MemberNodeState::Failed);
+
+            assert!(!election.update(&members, failed_at));
+            assert!(!election.update(&members, failed_at + LEASE / 2));
+            assert_eq!(Some(1), election.leadership().leader);
+            assert!(election.update(&members, failed_at + LEASE));
+            assert_eq!(Leadership { leader: Some(2), term: 1 << 16 | 2 }, election.leadership());
+        }
+
+        #[test]
+        fn test_election_steps_down_without_majority() {
+            let start = Instant::now();
+            let mut members = registry(&[(2, MemberNodeState::Alive), (3, MemberNodeState::Alive), (4, MemberNodeState::Alive)]);
+            let mut election = elected(1, &members, start);
+            assert!(election.is_leader());
+
+            members.set_node_state(3, MemberNodeState::Failed);
+            members.set_node_state(4, MemberNodeState::Failed);
+
+            assert!(election.update(&members, start + LEASE * 2));
+            assert_eq!(None, election.leadership().leader);
+        }
+
+        #[test]
+        fn test_election_majority_excludes_departed_members() {
+            let start = Instant::now();
+            let mut members = registry(&[(2, MemberNodeState::Alive), (3, MemberNodeState::Alive), (4, MemberNodeState::Alive)]);
+            let mut election = elected(1, &members, start);
+
+            members.set_node_state(2, MemberNodeState::Left);
+            members.set_node_state(3, MemberNodeState::Left);
+            assert!(!election.update(&members, start + LEASE * 2));
+            assert!(election.is_leader());
+
+            members.set_node_state(4, MemberNodeState::Failed);
+            assert!(election.update(&members, start + LEASE * 3));
+            assert_eq!(None, election.leadership().leader);
+
+            members.reap(Duration::ZERO);
+            assert!(!election.update(&members, start + LEASE * 4));
+            assert!(election.update(&members, start + LEASE * 5));
+            assert!(election.is_leader());
+        }
+
+        #[test]
+        fn test_election_hands_over_to_lower_stable_member() {
+            let start = Instant::now();
+            let mut members = registry(&[(3, MemberNodeState::Alive)]);
+            let mut election = elected(2, &members, start);
+            assert!(election.is_leader());
+            let joined_at = start + LEASE * 2;
+
+            members.add(1, 0);
+
+            assert!(!election.update(&members, joined_at));
+            assert!(election.update(&members, joined_at + STABILITY));
+            assert_eq!(Some(1), election.leadership().leader);
+        }
+
+        #[test]
+        fn test_election_leader_claims_term_above_observed_one() {
+            let start = Instant::now();
+            let members = registry(&[(2, MemberNodeState::Alive)]);
+            let mut leader = elected(1, &members, start);
+            let mut follower = elected(2, &registry(&[(1, MemberNodeState::Alive)]), start);
+
+            assert!(follower.observe_term(3 << 16 | 4));
+            assert_eq!(3 << 16 | 4, follower.leadership().term);
+            assert!(leader.observe_term(3 << 16 | 4));
+            assert_eq!(4 << 16 | 1, leader.leadership().term);
+            assert!(!leader.observe_term(2 << 16 | 5));
+        }
+    }
+
+    mod coordinate_tests {
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use rand::{Rng, thread_rng};
+        use crate::coordinate::swim_node::Coordinate;
+        use crate::member_node::swim_node::{DefaultMemberNode, MemberNodeDetails, NodeConfig};
+        use crate::message::swim_node::Message;
+        use crate::run_network_with_config;
+
+        #[test]
+        fn test_coordinates_converge_to_measured_rtts() {
+            let positions = [(0.0, 0.0), (0.03, 0.0), (0.0, 0.04), (0.03, 0.04), (0.1, 0.1)];
+            let rtt = |a: usize, b: usize| {
+                let (dx, dy): (f64, f64) = (positions[a].0 - positions[b].0, positions[a].1 - positions[b].1);
+                Duration::from_secs_f64((dx * dx + dy * dy).sqrt() + 0.002)
+            };
+            let mut coordinates = vec![Coordinate::new(); positions.len()];
+            let mut rng = thread_rng();
+
+            for _ in 0..20000 {
+                let (a, b) = (rng.gen_range(0..positions.len()), rng.gen_range(0..positions.len()));
+                if a != b {
+                    let other = coordinates[b].clone();
+                    coordinates[a].update(&other, rtt(a, b));
+                }
+            }
+
+            let mut errors = Vec::new();
+            for a in 0..positions.len() {
+                for b in (0..positions.len()).filter(|b| *b != a) {
+                    let (estimate, actual) = (coordinates[a].distance_to(&coordinates[b]).unwrap().as_secs_f64(), rtt(a, b).as_secs_f64());
+                    errors.push((estimate - actual).abs() / actual);
+                }
+            }
+            let mean = errors.iter().sum::<f64>() / errors.len() as f64;
+            assert!(mean < 0.15, "mean relative error {}", mean);
+            for a in 0..4 {
+                let farthest = (0..positions.len()).filter(|b| *b != a)
+                    .max_by_key(|b| coordinates[a].distance_to(&coordinates[*b]))
+                    .unwrap();
+                assert_eq!(4, farthest, "farthest member from {}", a);
+            }
+        }
+
+        #[test]
+        fn test_coordinate_ignores_invalid_peer() {
+            let mut coordinate = Coordinate::new();
+            let mut broken = Coordinate::new();
+            broken.vec[0] = f64::NAN;
+
+            coordinate.update(&broken, Duration::from_millis(10));
+
+            assert_eq!(Coordinate::new(), coordinate);
+        }
+
+        #[test]
+        fn test_node_ignores_invalid_peer_coordinates() {
+            let mut node = DefaultMemberNode::new(1, NodeConfig::default());
+            let mut below_ground = Coordinate::new();
+            below_ground.height = -1.0;
+            let mut broken = Coordinate::new();
+            broken.vec[0] = f64::NAN;
+
+            node.handle_message(Message::Ping(MemberNodeDetails::new(2), None, below_ground));
+            node.handle_message(Message::PingResponse(3, None, false, broken));
+
+            assert_eq!(None, node.estimate_rtt(1, 2));
+            assert_eq!(None, node.estimate_rtt(1, 3));
+            assert_eq!(None, Coordinate::new().distance_to(&Coordinate { height: -1.0, ..Coordinate::new() }));
+        }
+
+        #[test]
+        fn test_nodes_estimate_rtt_between_members() {
+            let mut router = run_network_with_config(NodeConfig { ping_interval: Duration::from_millis(20), ..NodeConfig::default() });
+            router.send(2, 1);
+            router.send(3, 1);
+
+            let started = Instant::now();
+            while router.estimate_rtt(1, 2, 3).is_none() {
+                assert!(started.elapsed() < Duration::from_secs(5), "node 1 didn't learn the coordinates");
+                thread::sleep(Duration::from_millis(20));
+            }
+            assert!(router.estimate_rtt(1, 1, 2).is_some());
+            assert_eq!(None, router.estimate_rtt(1, 1, 7));
+            router.shut_down();
+        }
+    }
+
+    mod latency_tests {
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use rand::thread_rng;
+        use crate::latency::swim_node::{LatencyDistribution, LatencyTopology};
+        use crate::member_node::swim_node::NodeConfig;
+        use crate::run_network_with_latency;
+
+        fn millis(ms: u64) -> Duration {
+            Duration::from_millis(ms)
+        }
+
+        #[test]
+        fn test_latency_distribution_by_zones() {
+            let mut topology = LatencyTopology::new(LatencyDistribution::Constant(millis(1)), LatencyDistribution::Constant(millis(20)));
+            topology.place(1, "a");
+            topology.place(2, "a");
+            topology.place(3, "b");
+            topology.place(4, "c");
+            topology.link("c", "a", LatencyDistribution::Constant(millis(80)));
+
+            assert_eq!(&LatencyDistribution::Constant(millis(1)), topology.distribution(1, 2));
+            assert_eq!(&LatencyDistribution::Constant(millis(20)), topology.distribution(1, 3));
+            assert_eq!(&LatencyDistribution::Constant(millis(80)), topology.distribution(4, 1));
+            assert_eq!(&LatencyDistribution::Constant(millis(20)), topology.distribution(3, 4));
+            assert_eq!(&LatencyDistribution::Constant(millis(20)), topology.distribution(1, 5));
+            assert_eq!(&LatencyDistribution::Constant(millis(1)), topology.distribution(5, 5));
+        }
+
+        #[test]
+        fn test_latency_samples_stay_in_range() {
+            let mut rng = thread_rng();
+            let mut topology = LatencyTopology::new(LatencyDistribution::Uniform(millis(5), millis(10)),
+                                                    LatencyDistribution::Normal(millis(2), millis(5)));
+            topology.place(1, "a");
+            topology.place(2, "a");
+            topology.set_jitter(millis(3));
+
+            for _ in 0..1000 {
+                let delay = topology.delay(1, 2, &mut rng);
+                assert!(delay >= millis(5) && delay < millis(13), "{:?}", delay);
+            }
+            let normal: Vec<Duration> = (0..1000).map(|_| LatencyDistribution::Normal(millis(2), millis(5)).sample(&mut rng)).collect();
+            assert!(normal.contains(&Duration::ZERO));
+            assert!(normal.iter().any(|d| *d > millis(5)));
+        }
+
+        #[test]
+        fn test_coordinates_follow_simulated_zones() {
+            let mut topology = LatencyTopology::new(LatencyDistribution::Constant(millis(1)), LatencyDistribution::Constant(millis(15)));
+            topology.place(1, "a");
+            topology.place(2, "a");
+            topology.place(3, "b");
+            topology.set_jitter(millis(1));
+            let mut router = run_network_with_latency(NodeConfig { ping_interval: millis(50), ..NodeConfig::default() }, topology);
+            router.send(2, 1);
+            router.send(3, 1);
+
+            let started = Instant::now();
+            loop {
+                let near = router.estimate_rtt(1, 1, 2);
+                let far = router.estimate_rtt(1, 1, 3);
+                if let (Some(near), Some(far)) = (near, far) {
+                    if far > millis(20) && far > near * 3 {
+                        break;
+                    }
+                }
+                assert!(started.elapsed() < Duration::from_secs(10), "estimates {:?} and {:?} don't reflect the zones", near, far);
+                thread::sleep(millis(50));
+            }
+            router.shut_down();
+        }
+    }
+
+    mod federation_tests {
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::connection::swim_node::ConnectionFactory;
+        use crate::federation::swim_node::{wan_config, Gateway};
+        use crate::health::swim_node::{HealthCheck, HealthConfig};
+        use crate::latency::swim_node::{LatencyDistribution, LatencyTopology};
+        use crate::member_node::swim_node::{MemberNode, MemberNodeHandle, MemberNodeState, NodeConfig};
+        use crate::network_router::{ClusterInsight, DefaultNodeFactory, DefaultNodeRequestRouter, NodeRequestRouter};
+
+        const LAN_PING_INTERVAL: Duration = Duration::from_millis(50);
+        const WAN_PING_INTERVAL: Duration = Duration::from_millis(150);
+
+        fn wait_until<F: Fn() -> bool>(condition: F, what: &str) {
+            let started = Instant::now();
+            while !condition() {
+                assert!(started.elapsed() < Duration::from_secs(5), "{}", what);
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+
+        fn start_cluster(size: u16) -> (Box<DefaultNodeRequestRouter<MemberNodeHandle>>, MemberNodeHandle) {
+            let node_factory = DefaultNodeFactory::with_config(NodeConfig { ping_interval: LAN_PING_INTERVAL, ..NodeConfig::default() });
+            let mut router = DefaultNodeRequestRouter::new(Box::new(node_factory), Arc::new(Mutex::new(ConnectionFactory::new())));
+            router.start();
+            for host in 2..=size {
+                router.send(host, 1);
+            }
+            let gateway = router.node(1).unwrap().clone();
+            (router, gateway)
+        }
+
+        fn wan_pool() -> Arc<Mutex<ConnectionFactory>> {
+            let mut topology = LatencyTopology::new(LatencyDistribution::Constant(Duration::from_millis(1)),
+                                                    LatencyDistribution::Uniform(Duration::from_millis(10), Duration::from_millis(20)));
+            topology.place(1, "east");
+            topology.place(2, "west");
+            let mut pool = ConnectionFactory::new();
+            pool.set_latency(topology);
+            Arc::new(Mutex::new(pool))
+        }
+
+        #[test]
+        fn test_gateways_see_remote_clusters() {
+            let pool = wan_pool();
+            let (east, east_node) = start_cluster(3);
+            let (west, west_node) = start_cluster(2);
+            let east_gateway = Gateway::start("east", east_node, 1, NodeConfig { ping_interval: WAN_PING_INTERVAL, ..wan_config(vec![]) }, pool.clone());
+            let west_gateway = Gateway::start("west", west_node, 2, NodeConfig { ping_interval: WAN_PING_INTERVAL, ..wan_config(vec![1]) }, pool);
+
+            wait_until(|| east_gateway.reachable_clusters() == vec![String::from("west")], "east doesn't reach west");
+            wait_until(|| west_gateway.reachable_clusters() == vec![String::from("east")], "west doesn't reach east");
+            assert_eq!(Some(&MemberNodeState::Alive), east_gateway.remote_gateways()["west"].get(&2));
+            assert_eq!(2, east_gateway.lan().details().unwrap().members().len());
+            assert_eq!(1, west_gateway.lan().details().unwrap().members().len());
+
+            west_gateway.shut_down();
+
+            wait_until(|| east_gateway.reachable_clusters().is_empty(), "east still reaches west");
+            assert_eq!(2, east.details(1).unwrap().members().len());
+            east_gateway.shut_down();
+            east.shut_down();
+            west.shut_down();
+        }
+
+        #[test]
+        fn test_degraded_gateway_keeps_its_cluster_reachable() {
+            let pool = wan_pool();
+            let (east, east_node) = start_cluster(1);
+            let (west, west_node) = start_cluster(1);
+            let health = HealthConfig { interval: Duration::ZERO, leave_after: None };
+            let east_gateway = Gateway::start("east", east_node, 1, NodeConfig { ping_interval: WAN_PING_INTERVAL, ..wan_config(vec![]) }, pool.clone());
+            let west_gateway = Gateway::start("west", west_node, 2, NodeConfig { ping_interval: WAN_PING_INTERVAL, health, ..wan_config(vec![1]) }, pool);
+            wait_until(|| east_gateway.reachable_clusters() == vec![String::from("west")], "east doesn't reach west");
+
+            west_gateway.wan().add_health_check("relay", HealthCheck::Closure(Box::new(|| Err(String::from("overloaded")))));
+
+            wait_until(|| east_gateway.remote_gateways()["west"].get(&2) == Some(&MemberNodeState::Degraded), "west gateway isn't degraded");
+            assert_eq!(vec![String::from("west")], east_gateway.reachable_clusters());
+            east_gateway.shut_down();
+            west_gateway.shut_down();
+            east.shut_down();
+            west.shut_down();
+        }
+
+        #[test]
+        fn test_wan_config_is_slower_than_lan() {
+            let config = wan_config(vec![1, 2]);
+
+            assert!(config.ping_interval > NodeConfig::default().ping_interval);
+            assert!(config.indirect_probes > NodeConfig::default().indirect_probes);
+            assert_eq!(vec![1, 2], config.seeds);
+        }
+    }
+
+    mod partition_tests {
+        use std::collections::{BTreeMap, BTreeSet};
         use std::sync::mpsc;
-        use std::sync::mpsc::Receiver;
-        use crate::connection::swim_node::{ConnectionFactory, ConnectionRegistry};
+        use std::time::{Duration, Instant};
+        use crate::event::swim_node::NodeEvent;
+        use crate::member_node::swim_node::{DefaultMemberNode, MemberNodeDetails, MemberNodeState, MemberNodesRegistry, NodeConfig};
         use crate::message::swim_node::Message;
+        use crate::partition::swim_node::{PartitionConfig, PartitionDetector};
+        use crate::rendezvous::swim_node::ZONE_TAG;
+        use crate::rpc::swim_node::Envelope;
+
+        fn registry(members: &[(u16, &str)]) -> MemberNodesRegistry {
+            let mut registry = MemberNodesRegistry::new();
+            for (host, zone) in members {
+                registry.add(*host, 0);
+                registry.set_tags(*host, BTreeMap::from([(String::from(ZONE_TAG), String::from(*zone))]));
+            }
+            registry
+        }
 
         #[test]
-        fn test_connection_create() {
-            let connection_factory = create_simple_connection_factory_with_receiver();
+        fn test_partition_suspected_on_mass_failure() {
+            let mut members = registry(&[(2, "a"), (3, "a"), (4, "b"), (5, "b")]);
+            let mut detector = PartitionDetector::new(PartitionConfig::default());
+            let now = Instant::now();
 
-            assert!(connection_factory.0.get_connection_for(1).is_some())
+            members.set_node_state(4, MemberNodeState::Failed);
+            assert_eq!(None, detector.update(&members, now));
+
+            members.set_node_state(5, MemberNodeState::Failed);
+            let suspicion = detector.update(&members, now + Duration::from_secs(1)).unwrap();
+            assert_eq!(BTreeSet::from([4, 5]), suspicion.members);
+            assert_eq!(BTreeSet::from([String::from("b")]), suspicion.zones);
+            assert_eq!(None, detector.update(&members, now + Duration::from_secs(2)));
+
+            members.add(4, 1);
+            members.add(5, 1);
+            detector.update(&members, now + Duration::from_secs(3));
+            assert_eq!(None, detector.suspicion());
         }
 
         #[test]
-        fn test_connection_delete() {
-            let mut connection_factory = create_simple_connection_factory_with_receiver();
-            connection_factory.0.remove_connection(1);
+        fn test_partition_not_suspected_for_spread_out_failures() {
+            let mut members = registry(&[(2, "a"), (3, "a"), (4, "b"), (5, "b")]);
+            let mut detector = PartitionDetector::new(PartitionConfig::default());
+            let now = Instant::now();
 
-            assert!(connection_factory.0.get_connection_for(1).is_none())
+            members.set_node_state(4, MemberNodeState::Failed);
+            detector.update(&members, now);
+            members.set_node_state(2, MemberNodeState::Failed);
+
+            assert_eq!(None, detector.update(&members, now + Duration::from_secs(60)));
         }
 
         #[test]
-        fn test_connection_send_and_receive() {
-            let connection_factory = create_simple_connection_factory_with_receiver();
+        fn test_partition_suspected_for_failed_zone() {
+            let hosts: Vec<(u16, &str)> = (2..12).map(|h| (h, if h < 4 { "b" } else { "a" })).collect();
+            let mut members = registry(&hosts);
+            let mut detector = PartitionDetector::new(PartitionConfig::default());
 
-            connection_factory.0.send_to(1, Message::Shutdown());
+            members.set_node_state(2, MemberNodeState::Failed);
+            members.set_node_state(3, MemberNodeState::Failed);
+            let suspicion = detector.update(&members, Instant::now()).unwrap();
 
-            match connection_factory.1.recv().unwrap() {
-                Message::Shutdown() => {}
-                _ => { panic!("Failed to send message from connection factory") }
+            assert_eq!(BTreeSet::from([2, 3]), suspicion.members);
+            assert_eq!(BTreeSet::from([String::from("b")]), suspicion.zones);
+        }
+
+        #[test]
+        fn test_member_node_throttles_failures_while_partition_suspected() {
+            let partition = PartitionConfig { throttle: true, ..PartitionConfig::default() };
+            let mut node = DefaultMemberNode::new(1, NodeConfig { partition: Some(partition), ..NodeConfig::default() });
+            let (sender, events) = mpsc::channel();
+            node.subscribe(sender);
+            for host in 2..5 {
+                node.handle_message(Message::Request(MemberNodeDetails::new(host), Envelope::new("hello", Vec::new())));
+            }
+            for _ in 0..30 {
+                node.handle_message(Message::Tick());
+            }
+
+            let states: Vec<MemberNodeState> = (2..5).map(|h| *node.details().members().get_state_for(h).unwrap()).collect();
+            assert_eq!(2, states.iter().filter(|s| **s == MemberNodeState::Failed).count());
+            assert_eq!(1, states.iter().filter(|s| **s == MemberNodeState::Suspected).count());
+            assert!(events.try_iter().any(|e| matches!(e, NodeEvent::PartitionSuspected(p) if p.members.len() == 2)));
+        }
+    }
+
+    mod health_tests {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::{Duration, Instant};
+        use crate::health::swim_node::{HealthCheck, HealthChecks, HealthConfig, HealthStatus};
+        use crate::member_node::swim_node::{DefaultMemberNode, MemberNodeState, MemberNodesRegistry, NodeConfig};
+        use crate::message::swim_node::Message;
+        use crate::rpc::swim_node::Envelope;
+
+        fn switch(healthy: &Arc<AtomicBool>) -> HealthCheck {
+            let healthy = Arc::clone(healthy);
+            HealthCheck::Closure(Box::new(move || if healthy.load(Ordering::SeqCst) { Ok(()) } else { Err(String::from("down")) }))
+        }
+
+        fn config(leave_after: Option<u32>) -> NodeConfig {
+            NodeConfig { health: HealthConfig { interval: Duration::ZERO, leave_after }, ..NodeConfig::default() }
+        }
+
+        #[test]
+        fn test_degraded_members_probe_indirectly() {
+            let mut members = MemberNodesRegistry::new();
+            members.merge(2, MemberNodeState::Degraded, 1);
+            members.add(3, 0);
+            members.set_node_state(3, MemberNodeState::Suspected);
+
+            assert_eq!(vec![2], members.get_random_nodes(3));
+        }
+
+        #[test]
+        fn test_health_checks_run_once_per_interval() {
+            let healthy = Arc::new(AtomicBool::new(false));
+            let mut checks = HealthChecks::new(HealthConfig { interval: Duration::from_secs(10), leave_after: Some(2) });
+            checks.register("service", switch(&healthy));
+            let now = Instant::now();
+
+            assert_eq!(Some(HealthStatus::Degraded(vec![(String::from("service"), String::from("down"))])), checks.run(now));
+            assert_eq!(None, checks.run(now + Duration::from_secs(1)));
+            assert_eq!(Some(HealthStatus::Leave), checks.run(now + Duration::from_secs(10)));
+
+            healthy.store(true, Ordering::SeqCst);
+            assert_eq!(Some(HealthStatus::Healthy), checks.run(now + Duration::from_secs(20)));
+        }
+
+        #[test]
+        fn test_command_health_check_reports_previous_run() {
+            let mut checks = HealthChecks::new(HealthConfig { interval: Duration::ZERO, leave_after: None });
+            checks.register("false", HealthCheck::Command(String::from("false"), Vec::new()));
+
+            assert_eq!(Some(HealthStatus::Healthy), checks.run(Instant::now()));
+            std::thread::sleep(Duration::from_millis(200));
+            assert!(matches!(checks.run(Instant::now()), Some(HealthStatus::Degraded(failed)) if failed[0].0 == "false"));
+        }
+
+        #[test]
+        fn test_degraded_node_is_gossiped_and_recovers() {
+            let healthy = Arc::new(AtomicBool::new(false));
+            let mut node1 = DefaultMemberNode::new(1, config(None));
+            let mut node2 = DefaultMemberNode::new(2, NodeConfig::default());
+            node1.add_health_check("service", switch(&healthy));
+            node1.handle_message(Message::Request(node2.serialize_host_details(), Envelope::new("hello", Vec::new())));
+
+            let ping = node1.handle_message(Message::Tick()).into_iter().find(|(to, _)| *to == 2).unwrap().1;
+            assert_eq!(MemberNodeState::Degraded, *node1.details().state());
+            node2.handle_message(ping);
+            assert_eq!(Some(&MemberNodeState::Degraded), node2.details().members().get_state_for(1));
+
+            let ping = node2.handle_message(Message::Tick()).remove(0).1;
+            let ack = node1.handle_message(ping).into_iter().find(|(to, _)| *to == 2).unwrap().1;
+            assert!(matches!(ack, Message::PingResponse(1, None, false, _)));
+            node2.handle_message(ack);
+            assert_eq!(Some(&MemberNodeState::Degraded), node2.details().members().get_state_for(1));
+
+            healthy.store(true, Ordering::SeqCst);
+            let ping = node1.handle_message(Message::Tick()).into_iter().find(|(to, _)| *to == 2).unwrap().1;
+            node2.handle_message(ping);
+            assert_eq!(Some(&MemberNodeState::Alive), node2.details().members().get_state_for(1));
+            assert_eq!(2, node1.details().incarnation());
+        }
+
+        #[test]
+        fn test_node_leaves_when_checks_fail_persistently() {
+            let healthy = Arc::new(AtomicBool::new(false));
+            let mut node1 = DefaultMemberNode::new(1, config(Some(3)));
+            let mut node2 = DefaultMemberNode::new(2, NodeConfig::default());
+            node1.add_health_check("service", switch(&healthy));
+            node1.handle_message(Message::Request(node2.serialize_host_details(), Envelope::new("hello", Vec::new())));
+
+            let mut leave = None;
+            for _ in 0..3 {
+                leave = node1.handle_message(Message::Tick()).into_iter()
+                    .find(|(_, m)| matches!(m, Message::Request(_, envelope) if envelope.kind == "leave"));
             }
+            assert_eq!(MemberNodeState::Left, *node1.details().state());
+
+            let (to, message) = leave.unwrap();
+            assert_eq!(2, to);
+            node2.handle_message(message);
+            assert_eq!(Some(&MemberNodeState::Left), node2.details().members().get_state_for(1));
+            assert!(node1.handle_message(Message::Tick()).is_empty());
+        }
+    }
+
+    mod rate_limit_tests {
+        use std::time::{Duration, Instant};
+        use crate::connection::swim_node::{ConnectionFactory, ConnectionRegistry};
+        use crate::coordinate::swim_node::Coordinate;
+        use crate::member_node::swim_node::MemberNodeDetails;
+        use crate::message::swim_node::{Message, MessagePriority};
+        use crate::rate_limit::swim_node::{RateLimit, RateLimiter};
+        use crate::rpc::swim_node::Envelope;
+
+        #[test]
+        fn test_gossip_leaves_reserve_for_acks_and_probes() {
+            let mut limiter = RateLimiter::new(RateLimit { messages_per_sec: 10.0, bytes_per_sec: 1.0e6, reserve: 0.5 });
+            let now = Instant::now();
+
+            assert_eq!(5, (0..10).filter(|_| limiter.admit(1, 10, MessagePriority::Gossip, now)).count());
+            assert_eq!(5, (0..10).filter(|_| limiter.admit(1, 10, MessagePriority::Ack, now)).count());
+            assert!(!limiter.admit(1, 10, MessagePriority::Probe, now));
+            assert!(limiter.admit(2, 10, MessagePriority::Probe, now));
+            assert!(limiter.admit(1, 10, MessagePriority::Probe, now + Duration::from_millis(100)));
+            assert!(limiter.admit(1, 10, MessagePriority::Control, now));
+        }
+
+        #[test]
+        fn test_large_message_is_paid_back_before_next_one() {
+            let mut limiter = RateLimiter::new(RateLimit { messages_per_sec: 100.0, bytes_per_sec: 1000.0, reserve: 0.0 });
+            let now = Instant::now();
+
+            assert!(limiter.admit(1, 3000, MessagePriority::Gossip, now));
+            assert!(!limiter.admit(1, 10, MessagePriority::Ack, now + Duration::from_secs(1)));
+            assert!(limiter.admit(1, 10, MessagePriority::Ack, now + Duration::from_millis(2100)));
         }
 
-        fn create_simple_connection_factory_with_receiver() -> (ConnectionFactory, Receiver<Message>) {
-            let channel = mpsc::channel();
+        #[test]
+        fn test_connection_drops_messages_over_budget() {
             let mut connection_factory = ConnectionFactory::new();
-            connection_factory.add_connection(1, channel.0);
+            connection_factory.set_rate_limit(RateLimit { messages_per_sec: 2.0, bytes_per_sec: 1.0e6, reserve: 0.5 });
+            let (sender, receiver) = connection_factory.inbox();
+            connection_factory.add_connection(2, sender);
 
-            (connection_factory, channel.1)
+            for _ in 0..2 {
+                connection_factory.send_from(1, 2, Message::Request(MemberNodeDetails::new(1), Envelope::new("hello", Vec::new())));
+            }
+            connection_factory.send_from(1, 2, Message::PingResponse(1, None, false, Coordinate::new()));
+            connection_factory.send_to(2, Message::Tick());
+
+            assert_eq!(1, connection_factory.throttled_messages());
+            assert!(matches!(receiver.recv(), Some(Message::Request(..))));
+            assert!(matches!(receiver.recv(), Some(Message::PingResponse(..))));
+            assert!(matches!(receiver.recv(), Some(Message::Tick())));
         }
     }
 
-    mod member_node_tests {
+    mod compression_tests {
+        use std::collections::BTreeMap;
         use std::sync::{Arc, Mutex};
         use std::thread;
-        use std::time::Duration;
-        use crate::connection::swim_node::{ConnectionFactory, ConnectionRegistry};
-        use crate::member_node::swim_node::{DefaultMemberNode, MemberNodeState};
+        use std::time::{Duration, Instant};
+        use crate::compression::swim_node::{accepts_compression, decode_frame, encode_frame, Compression, FLAG_COMPRESSED};
+        use crate::connection::swim_node::{send_all, ConnectionFactory, ConnectionRegistry};
+        use crate::coordinate::swim_node::Coordinate;
+        use crate::member_node::swim_node::{DefaultMemberNode, MemberNodeDetails, MemberNodeHandle, MemberNodeState, MemberNodesRegistry, NodeConfig};
         use crate::message::swim_node::Message;
+        use crate::query::swim_node::Query;
+        use crate::rpc::swim_node::Envelope;
+
+        fn large_details(host: u16) -> MemberNodeDetails {
+            let mut members = MemberNodesRegistry::new();
+            for member in 1..200 {
+                members.add(member, 0);
+                members.set_tags(member, BTreeMap::from([(String::from("zone"), format!("zone-{}", member % 3))]));
+            }
+            members.set_node_state(7, MemberNodeState::Failed);
+            MemberNodeDetails::with_members(host, members)
+        }
 
         #[test]
-        fn test_member_nodes_sending_message() {
-            let connection_factory = ConnectionFactory::new();
-            let connection_ref = Arc::new(Mutex::new(connection_factory));
-            let node1 = DefaultMemberNode::new(1, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
-            let node2 = DefaultMemberNode::new(2, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
+        fn test_encoded_len_matches_encoding() {
+            let tags = BTreeMap::from([(String::from("role"), String::from("db"))]);
+            let mut node = DefaultMemberNode::new(1, NodeConfig { tags, ..NodeConfig::default() });
+            node.broadcast_event("deploy", b"v2".to_vec());
+            let details = node.serialize_host_details();
+            assert_eq!(1, details.events().len());
+            let query = Query { id: 3, name: String::from("load"), payload: b"cpu".to_vec(), filter: BTreeMap::from([(String::from("role"), String::from("db"))]) };
+            let messages = vec![
+                Message::Request(large_details(1), Envelope::new("hello", b"hello".to_vec())),
+                Message::Response(details, Envelope::new("hello", Vec::new())),
+                Message::Ping(large_details(1), Some(MemberNodeDetails::new(2)), Coordinate::new()),
+                Message::PingResponse(2, None, false, Coordinate::new()),
+                Message::ProbeRequest(MemberNodeDetails::new(1), 2),
+                Message::ProbeResponse(2, true),
+                Message::Query(1, query),
+                Message::QueryAck(2, 3),
+                Message::QueryResponse(2, 3, b"42".to_vec()),
+                Message::Tick(),
+            ];
 
-            let serialized_details = node1.lock().unwrap().details().serialize();
-            connection_ref.lock().unwrap().send_to(2, Message::Request(serialized_details, String::from("hello")));
+            for message in messages {
+                let encoded = message.encode();
+                assert_eq!(message.encoded_len(), encoded.len());
+                if !encoded.is_empty() {
+                    assert_eq!(encoded, Message::decode(&encoded).unwrap().encode());
+                }
+            }
+        }
 
-            thread::sleep(Duration::from_secs(1));
+        #[test]
+        fn test_decode_rejects_malformed_messages() {
+            let encoded = Message::Ping(large_details(1), None, Coordinate::new()).encode();
 
-            assert_eq!(MemberNodeState::Alive, *node1.lock().unwrap().details().members().get_state_for(2).unwrap());
-            assert_eq!(MemberNodeState::Alive, *node2.lock().unwrap().details().members().get_state_for(1).unwrap());
+            assert!(Message::decode(&encoded[..encoded.len() - 1]).is_err());
+            assert!(Message::decode(&[encoded.as_slice(), &[0]].concat()).is_err());
+            assert!(Message::decode(&[42]).is_err());
         }
 
         #[test]
-        fn test_member_nodes_when_one_times_out() {
-            let connection_factory = ConnectionFactory::new();
+        fn test_frame_compressed_only_for_large_messages_to_accepting_peers() {
+            let compression = Compression::default();
+            let large = Message::Ping(large_details(1), None, Coordinate::new()).encode();
+            let small = Message::ProbeResponse(2, true).encode();
+
+            let frame = encode_frame(&large, Some(&compression), true);
+            assert_ne!(0, frame[0] & FLAG_COMPRESSED);
+            assert!(frame.len() * 2 < large.len());
+            assert!(accepts_compression(&frame));
+            assert_eq!(Ok(large.clone()), decode_frame(&frame));
+
+            assert_eq!(large.len(), encode_frame(&large, Some(&compression), false).len());
+            assert_eq!(small.len(), encode_frame(&small, Some(&compression), true).len());
+            assert_eq!(large, encode_frame(&large, None, true));
+            assert!(!accepts_compression(&large));
+        }
+
+        #[test]
+        fn test_corrupted_frame_is_rejected() {
+            let large = Message::Ping(large_details(1), None, Coordinate::new()).encode();
+            let mut frame = encode_frame(&large, Some(&Compression::default()), true);
+            frame.truncate(frame.len() / 2);
+
+            assert!(decode_frame(&frame).is_err());
+            assert!(decode_frame(&[FLAG_COMPRESSED, 0xff, 0xff, 0xff, 0xff]).is_err());
+        }
+
+        #[test]
+        fn test_compression_negotiated_between_hosts() {
+            let mut connection_factory = ConnectionFactory::new();
+            connection_factory.set_compression(1, Compression::default());
+            connection_factory.set_compression(2, Compression::default());
+            let mut receivers = Vec::new();
+            for host in 1..4 {
+                let (sender, receiver) = connection_factory.inbox();
+                connection_factory.add_connection(host, sender);
+                receivers.push(receiver);
+            }
+            let ping = |from: u16| Message::Ping(large_details(from), None, Coordinate::new());
+
+            connection_factory.send_from(1, 2, ping(1));
+            assert_eq!(0, connection_factory.saved_bytes());
+            connection_factory.send_from(2, 1, ping(2));
+            let saved = connection_factory.saved_bytes();
+            assert!(saved > 0);
+
+            connection_factory.send_from(3, 1, ping(3));
+            connection_factory.send_from(1, 3, ping(1));
+            assert_eq!(saved, connection_factory.saved_bytes());
+
+            connection_factory.send_from(1, 2, ping(1));
+            assert!(connection_factory.saved_bytes() > saved);
+            assert!(matches!(receivers[1].recv(), Some(Message::Ping(..))));
+        }
+
+        #[test]
+        fn test_route_frames_messages_after_registry_is_unlocked() {
+            let mut connection_factory = ConnectionFactory::new();
+            connection_factory.set_compression(1, Compression::default());
+            connection_factory.set_compression(2, Compression::default());
+            let (sender, _receiver1) = connection_factory.inbox();
+            connection_factory.add_connection(1, sender);
+            let (sender, receiver2) = connection_factory.inbox();
+            connection_factory.add_connection(2, sender);
             let connection_ref = Arc::new(Mutex::new(connection_factory));
-            let node1 = DefaultMemberNode::new(1, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
-            let node2 = DefaultMemberNode::new(2, Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref));
+            send_all(&*connection_ref, 2, vec![(1, Message::Ping(large_details(2), None, Coordinate::new()))]);
+
+            let route = connection_ref.lock().unwrap().route(1, 2).unwrap();
+            // Sending would deadlock if framing still needed the registry.
+            let guard = connection_ref.lock().unwrap();
+            route.send(Message::Ping(large_details(1), None, Coordinate::new()));
+            drop(guard);
+
+            assert!(connection_ref.lock().unwrap().saved_bytes() > 0);
+            assert!(matches!(receiver2.recv(), Some(Message::Ping(from, ..)) if from.members().len() == 199));
+            connection_ref.lock().unwrap().partition(vec![vec![1], vec![2]]);
+            assert!(connection_ref.lock().unwrap().route(1, 2).is_none());
+        }
 
-            let serialized_details = node1.lock().unwrap().details().serialize();
-            connection_ref.lock().unwrap().send_to(2, Message::Request(serialized_details, String::from("hello")));
+        #[test]
+        fn test_compression_forgotten_when_peer_restarts_without_it() {
+            let mut connection_factory = ConnectionFactory::new();
+            connection_factory.set_compression(1, Compression::default());
+            connection_factory.set_compression(2, Compression::default());
+            let (sender, _receiver1) = connection_factory.inbox();
+            connection_factory.add_connection(1, sender);
+            let (sender, _receiver2) = connection_factory.inbox();
+            connection_factory.add_connection(2, sender);
+            let ping = |from: u16| Message::Ping(large_details(from), None, Coordinate::new());
+            connection_factory.send_from(2, 1, ping(2));
+            connection_factory.send_from(1, 2, ping(1));
+            let saved = connection_factory.saved_bytes();
+            assert!(saved > 0);
 
-            node2.lock().unwrap().change_state(MemberNodeState::Failed);
+            connection_factory.disable_compression(2);
+            let (sender, receiver2) = connection_factory.inbox();
+            connection_factory.add_connection(2, sender);
+            connection_factory.send_from(1, 2, ping(1));
 
-            thread::sleep(Duration::from_secs(2));
+            assert_eq!(saved, connection_factory.saved_bytes());
+            assert!(matches!(receiver2.recv(), Some(Message::Ping(from, ..)) if from.members().len() == 199));
+        }
 
-            assert_eq!(MemberNodeState::Suspected, *node1.lock().unwrap().details().members().get_state_for(2).unwrap());
+        #[test]
+        fn test_nodes_with_and_without_compression_interoperate() {
+            let mut connection_factory = ConnectionFactory::new();
+            for host in [1, 3] {
+                connection_factory.set_compression(host, Compression { threshold: 0 });
+            }
+            let connection_ref = Arc::new(Mutex::new(connection_factory));
+            let config = NodeConfig { ping_interval: Duration::from_millis(50), ..NodeConfig::default() };
+            let nodes: Vec<MemberNodeHandle> = (1..4)
+                .map(|host| DefaultMemberNode::start_with_config(host, config.clone(), Arc::<Mutex<ConnectionFactory>>::clone(&connection_ref)))
+                .collect();
+            for node in nodes.iter().skip(1) {
+                let details = node.details().unwrap();
+                connection_ref.lock().unwrap().send_from(details.host(), 1, Message::Request(details, Envelope::new("hello", Vec::new())));
+            }
 
-            thread::sleep(Duration::from_secs(2));
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let converged = || nodes.iter().all(|node| {
+                let details = node.details().unwrap();
+                (1..4).filter(|h| *h != details.host()).all(|h| details.members().get_state_for(h) == Some(&MemberNodeState::Alive))
+            });
+            while !converged() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(50));
+            }
 
-            assert_eq!(MemberNodeState::Failed, *node1.lock().unwrap().details().members().get_state_for(2).unwrap());
+            assert!(converged());
+            assert!(connection_ref.lock().unwrap().saved_bytes() > 0);
+            nodes.iter().for_each(MemberNodeHandle::shut_down);
         }
     }
 
     mod test_router {
         use std::sync::{Arc, Mutex};
-        use crate::member_node::swim_node::{MemberNode, MemberNodeDetails};
+        use std::collections::BTreeMap;
+        use std::sync::mpsc::Receiver;
+        use std::time::Duration;
+        use crate::election::swim_node::Leadership;
+        use crate::health::swim_node::HealthCheck;
+        use crate::event::swim_node::NodeEvent;
+        use crate::query::swim_node::{QueryEvent, QueryHandler, QueryParams};
+        use crate::rpc::swim_node::RequestHandler;
+        use crate::member_node::swim_node::{MemberNode, MemberNodeDetails, MemberNodeState};
         use mockall::*;
         use mockall::predicate::*;
-        use crate::connection::swim_node::{ConnectionRegistry};
-        use crate::network_router::{DefaultNodeRequestRouter, NodeFactory, NodeRequestRouter};
-        use std::sync::mpsc::Sender;
+        use crate::connection::swim_node::{ConnectionRegistry, InboxReceiver, InboxSender, Route};
+        use crate::network_router::{ClusterInsight, DefaultNodeRequestRouter, FaultInjection, NodeFactory, NodeRequestRouter};
         use crate::message::swim_node::Message;
 
         mock! {
             TestMemberNode {}
             impl MemberNode for TestMemberNode {
                 fn host(&self) -> u16;
-                fn serialize_host_details(&self) -> MemberNodeDetails;
+                fn serialize_host_details(&self) -> Option<MemberNodeDetails>;
+                fn change_state(&self, state: MemberNodeState);
+                fn subscribe(&self) -> Receiver<NodeEvent>;
+                fn broadcast_event(&self, name: &str, payload: Vec<u8>);
+                fn set_tags(&self, tags: BTreeMap<String, String>);
+                fn handle_query(&self, name: &str, handler: QueryHandler);
+                fn handle_requests(&self, kind: &str, handler: RequestHandler);
+                fn request(&self, to: u16, kind: &str, body: Vec<u8>, timeout: Duration) -> Receiver<Vec<u8>>;
+                fn query(&self, name: &str, payload: Vec<u8>, params: QueryParams) -> Receiver<QueryEvent>;
+                fn leadership(&self) -> Option<Leadership>;
+                fn estimate_rtt(&self, from: u16, to: u16) -> Option<Duration>;
+                fn add_health_check(&self, name: &str, check: HealthCheck);
             }
         }
 
         mock! {
             TestNodeFactory {}
             impl NodeFactory<MockTestMemberNode> for TestNodeFactory {
-                fn create(&self, id: u16, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> Arc<Mutex<MockTestMemberNode>>;
+                fn create(&self, id: u16, incarnation: u32, connection: Arc<Mutex<dyn ConnectionRegistry>>) -> MockTestMemberNode;
             }
         }
 
@@ -117,8 +2106,13 @@ mod tests {
             TestConnectionRegistry {}
             impl ConnectionRegistry for TestConnectionRegistry {
                 fn send_to(&self, host: u16, message: Message);
-                fn add_connection(&mut self, host: u16, connection: Sender<Message>);
+                fn send_from(&self, from: u16, to: u16, message: Message);
+                fn route(&self, from: u16, to: u16) -> Option<Route>;
+                fn partition(&mut self, groups: Vec<Vec<u16>>);
+                fn heal(&mut self);
+                fn add_connection(&mut self, host: u16, connection: InboxSender);
                 fn remove_connection(&mut self, host: u16);
+                fn inbox(&self) -> (InboxSender, InboxReceiver);
             }
         }
 
@@ -127,27 +2121,27 @@ mod tests {
             let mut node1 = MockTestMemberNode::new();
             node1.expect_host().returning(|| 1);
             node1.expect_serialize_host_details()
-                .returning(|| MemberNodeDetails::new(1));
+                .returning(|| Some(MemberNodeDetails::new(1)));
 
             let mut node_factory = MockTestNodeFactory::new();
             node_factory.expect_create()
-                .withf(|host: &u16, _: &Arc<Mutex<dyn ConnectionRegistry>>| *host == 1)
-                .return_const(Arc::new(Mutex::new(node1)));
+                .withf(|host: &u16, _: &u32, _: &Arc<Mutex<dyn ConnectionRegistry>>| *host == 1)
+                .return_once(move |_, _, _| node1);
 
             let mut node2 = MockTestMemberNode::new();
             node2.expect_host().returning(|| 2);
             node2.expect_serialize_host_details()
-                .returning(|| MemberNodeDetails::new(2));
+                .returning(|| Some(MemberNodeDetails::new(2)));
 
             node_factory.expect_create()
-                .withf(|host: &u16, _: &Arc<Mutex<dyn ConnectionRegistry>>| *host == 2)
-                .return_const(Arc::new(Mutex::new(node2)));
+                .withf(|host: &u16, _: &u32, _: &Arc<Mutex<dyn ConnectionRegistry>>| *host == 2)
+                .return_once(move |_, _, _| node2);
 
             let mut connection_registry = MockTestConnectionRegistry::new();
-            connection_registry.expect_send_to()
-                .withf(|host: &u16, message: &Message|
+            connection_registry.expect_send_from()
+                .withf(|from: &u16, host: &u16, message: &Message|
                     match message {
-                        Message::Request(n, d) => *host == 1 && n.host() == 2 && *d == String::from("hello from 2"),
+                        Message::Request(n, d) => *from == 2 && *host == 1 && n.host() == 2 && d.body == b"hello from 2",
                         _ => false,
                     })
                 .return_const(());
@@ -156,5 +2150,54 @@ mod tests {
             router.start();
             router.send(2, 1)
         }
+
+        #[test]
+        fn test_router_send_from_stopped_node() {
+            let mut node1 = MockTestMemberNode::new();
+            node1.expect_host().returning(|| 1);
+            node1.expect_serialize_host_details().returning(|| None);
+
+            let mut node_factory = MockTestNodeFactory::new();
+            node_factory.expect_create().return_once(move |_, _, _| node1);
+
+            let mut connection_registry = MockTestConnectionRegistry::new();
+            connection_registry.expect_send_from().times(0);
+
+            let mut router = DefaultNodeRequestRouter::new(Box::<MockTestNodeFactory>::new(node_factory), Arc::new(Mutex::new(connection_registry)));
+            router.send(1, 2);
+            assert!(router.details(1).is_none());
+        }
+
+        #[test]
+        fn test_router_restart_crashes_node_and_starts_next_incarnation() {
+            let mut node_factory = MockTestNodeFactory::new();
+            let mut sequence = Sequence::new();
+            for expected_incarnation in 0..2 {
+                let mut node = MockTestMemberNode::new();
+                node.expect_host().returning(|| 1);
+                node_factory.expect_create()
+                    .withf(move |host: &u16, incarnation: &u32, _: &Arc<Mutex<dyn ConnectionRegistry>>|
+                        *host == 1 && *incarnation == expected_incarnation)
+                    .times(1)
+                    .in_sequence(&mut sequence)
+                    .return_once(move |_, _, _| node);
+            }
+
+            let mut connection_registry = MockTestConnectionRegistry::new();
+            connection_registry.expect_send_to()
+                .withf(|host: &u16, message: &Message| *host == 1 && matches!(message, Message::Shutdown()))
+                .times(1)
+                .return_const(());
+            connection_registry.expect_remove_connection()
+                .withf(|host: &u16| *host == 1)
+                .times(1)
+                .return_const(());
+
+            let mut router = DefaultNodeRequestRouter::new(Box::<MockTestNodeFactory>::new(node_factory), Arc::new(Mutex::new(connection_registry)));
+            router.start();
+            router.crash(1);
+            assert!(router.details(1).is_none());
+            router.restart(1);
+        }
     }
 }
\ No newline at end of file
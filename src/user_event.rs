@@ -0,0 +1,101 @@
+pub mod swim_node {
+    use std::collections::{HashMap, HashSet, VecDeque};
+
+    const MAX_SEEN_EVENTS: usize = 1024;
+    const RETRANSMIT_MULT: usize = 3;
+
+    /// An application event disseminated to every member by piggybacking on protocol messages.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct UserEvent {
+        pub name: String,
+        pub payload: Vec<u8>,
+        /// Lamport time of the event, events with the same name are ordered by it.
+        pub ltime: u64,
+        pub origin: u16,
+    }
+
+    /// Lamport clock, deduplication and dissemination state of the user events of a node.
+    pub struct UserEvents {
+        clock: u64,
+        seen: HashSet<(u16, u64)>,
+        seen_order: VecDeque<(u16, u64)>,
+        latest: HashMap<String, u64>,
+        pending: Vec<(UserEvent, usize)>,
+    }
+
+    impl Default for UserEvents {
+        fn default() -> Self {
+            UserEvents::new()
+        }
+    }
+
+    impl UserEvents {
+        pub fn new() -> UserEvents {
+            UserEvents {
+                clock: 0,
+                seen: HashSet::new(),
+                seen_order: VecDeque::new(),
+                latest: HashMap::new(),
+                pending: Vec::new(),
+            }
+        }
+
+        pub fn clock(&self) -> u64 {
+            self.clock
+        }
+
+        /// Creates an event of this node at the next Lamport time and queues it for dissemination.
+        pub fn broadcast(&mut self, origin: u16, name: &str, payload: Vec<u8>) -> UserEvent {
+            self.clock += 1;
+            let event = UserEvent { name: String::from(name), payload, ltime: self.clock, origin };
+            self.receive(event.clone());
+            event
+        }
+
+        /// Handles an event gossiped by another node. Returns the event if it should be delivered, or `None`
+        /// if it was seen before or a later event with the same name has already been delivered.
+        pub fn receive(&mut self, event: UserEvent) -> Option<UserEvent> {
+            if event.ltime >= self.clock {
+                self.clock = event.ltime + 1;
+            }
+            if !self.remember((event.origin, event.ltime)) {
+                return None;
+            }
+            if self.latest.get(&event.name).is_some_and(|latest| *latest > event.ltime) {
+                return None;
+            }
+            self.latest.insert(event.name.clone(), event.ltime);
+            self.pending.retain(|(e, _)| e.name != event.name);
+            self.pending.push((event.clone(), 0));
+            Some(event)
+        }
+
+        /// Events to piggyback on outgoing messages.
+        pub fn piggyback(&self) -> Vec<UserEvent> {
+            self.pending.iter().map(|(e, _)| e.clone()).collect()
+        }
+
+        /// Ages the pending events by one protocol period and stops gossiping the ones which have been
+        /// sent long enough to reach a cluster of `members` nodes.
+        pub fn expire(&mut self, members: usize) {
+            let limit = RETRANSMIT_MULT * ((members + 1) as f64).log2().ceil().max(1.0) as usize;
+            for (_, age) in self.pending.iter_mut() {
+                *age += 1;
+            }
+            self.pending.retain(|(_, age)| *age <= limit);
+        }
+
+        fn remember(&mut self, id: (u16, u64)) -> bool {
+            if !self.seen.insert(id) {
+                return false;
+            }
+            self.seen_order.push_back(id);
+            if self.seen_order.len() > MAX_SEEN_EVENTS {
+                if let Some(oldest) = self.seen_order.pop_front() {
+                    self.seen.remove(&oldest);
+                }
+            }
+            true
+        }
+    }
+}